@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::instruction::Instruction;
+use crate::object::Object;
+use crate::parser::{
+    parse_file_with_options, parse_object, DebugInfo, ParseError, ParseOptions, Span, Warning,
+};
+
+//assembles programs with whatever options it's been set up with, for using patp as a library
+//  let program = Assembler::new().case_insensitive(true).assemble("loop: DEC\nBNZ LOOP\nSTOP")?;
+#[derive(Clone, Debug, Default)]
+pub struct Assembler {
+    options: ParseOptions,
+    path: PathBuf,
+}
+
+//everything about an assembled program: the bytes to load, and what tools need to map them back to the source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub instructions: Vec<Instruction>,
+    pub symbols: BTreeMap<String, usize>,
+    //the file and line each address was assembled from, which can be an included file or a macro's body
+    pub source_map: BTreeMap<u8, Span>,
+    //any warnings, as a program with errors doesn't assemble
+    pub diagnostics: Vec<Diagnostic>,
+    debug_info: DebugInfo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+//an error or warning about a program, with where it is and the same rendering as the command line prints
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    //what to put in an allow() comment to turn a warning off
+    pub name: Option<&'static str>,
+    pub rendered: String,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    //use all the options given, replacing any set before
+    pub fn options(mut self, options: ParseOptions) -> Assembler {
+        self.options = options;
+        self
+    }
+
+    //treat symbols the same whatever case they're written in
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Assembler {
+        self.options.case_insensitive = case_insensitive;
+        self
+    }
+
+    //add extra names for mnemonics from a config file's contents, which has one per line as ALIAS = MNEMONIC
+    //path is only used to say where any errors are
+    pub fn aliases(
+        mut self,
+        config: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<Assembler, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        match self.options.add_aliases(config, path) {
+            Ok(()) => Ok(self),
+            Err(errors) => Err(errors
+                .iter()
+                .map(|error| Diagnostic::error(error, config, path))
+                .collect()),
+        }
+    }
+
+    //the file the source is from, which spans name and includes are looked for next to
+    //without one, includes are relative to the current directory
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Assembler {
+        self.path = path.into();
+        self
+    }
+
+    //assemble a program, returning every error found if it can't be
+    pub fn assemble(&self, source: &str) -> Result<Program, Vec<Diagnostic>> {
+        let (instructions, debug_info) = parse_file_with_options(source, &self.path, &self.options)
+            .map_err(|errors| self.errors(&errors, source))?;
+
+        //anything that made it through the parser has already been range checked
+        let bytes = instructions
+            .iter()
+            .map(|i| i.assemble().expect("assembled out of range operand"))
+            .collect();
+        let diagnostics = self.warnings(&debug_info, source);
+        Ok(Program {
+            bytes,
+            instructions,
+            symbols: debug_info
+                .symbols
+                .iter()
+                .map(|(name, value)| (name.clone(), *value))
+                .collect(),
+            source_map: debug_info.locations.clone(),
+            diagnostics,
+            debug_info,
+        })
+    }
+
+    //assemble one module of a program into an object to be linked with others, along with any warnings
+    pub fn assemble_object(
+        &self,
+        source: &str,
+    ) -> Result<(Object, Vec<Diagnostic>), Vec<Diagnostic>> {
+        let (object, debug_info) = parse_object(source, &self.path, &self.options)
+            .map_err(|errors| self.errors(&errors, source))?;
+        Ok((object, self.warnings(&debug_info, source)))
+    }
+
+    fn errors(&self, errors: &[ParseError], source: &str) -> Vec<Diagnostic> {
+        errors
+            .iter()
+            .map(|error| Diagnostic::error(error, source, &self.path))
+            .collect()
+    }
+
+    fn warnings(&self, debug_info: &DebugInfo, source: &str) -> Vec<Diagnostic> {
+        debug_info
+            .warnings
+            .iter()
+            .map(|warning| Diagnostic::warning(warning, source, &self.path))
+            .collect()
+    }
+}
+
+impl Program {
+    //the symbols and source map in the form the debugger and listings use
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+}
+
+impl Diagnostic {
+    fn error(error: &ParseError, source: &str, path: &Path) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: error.to_string(),
+            span: error.span().cloned(),
+            name: None,
+            rendered: error.render(source, path),
+        }
+    }
+
+    fn warning(warning: &Warning, source: &str, path: &Path) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: warning.to_string(),
+            span: Some(warning.span().clone()),
+            name: Some(warning.name()),
+            rendered: warning.render(source, path),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+//the same as the command line prints
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.rendered)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn program() {
+        let program = Assembler::new()
+            .assemble("start: LOAD x\nloop: DEC\nBNZ loop\nSTOP\nx: DB 3")
+            .unwrap();
+        assert_eq!(program.bytes, [0b1100_0100, 0b0110_0000, 0b1010_0001, 1, 3]);
+        assert_eq!(program.instructions[1], Instruction::Dec);
+        assert_eq!(
+            program.symbols.into_iter().collect::<Vec<_>>(),
+            [
+                ("loop".to_owned(), 1),
+                ("start".to_owned(), 0),
+                ("x".to_owned(), 4)
+            ]
+        );
+        assert_eq!(
+            program.source_map[&2],
+            Span {
+                file: String::new(),
+                line: 3,
+                column: 1,
+                len: 8
+            }
+        );
+
+        let warning = &program.diagnostics[0];
+        assert_eq!(program.diagnostics.len(), 1);
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.message, "Label start is never used");
+        assert_eq!(warning.name, Some("unused_label"));
+        assert_eq!(warning.span.as_ref().map(|span| span.line), Some(1));
+        assert!(warning
+            .to_string()
+            .starts_with("warning: Label start is never used\n --> 1:1\n"));
+    }
+
+    #[test]
+    fn options() {
+        let assembler = Assembler::new()
+            .case_insensitive(true)
+            .aliases("HALT = STOP", "course.cfg")
+            .unwrap();
+        assert_eq!(
+            assembler
+                .assemble("Loop: DEC\nBNZ LOOP\nhalt")
+                .unwrap()
+                .bytes,
+            [0b0110_0000, 0b1010_0000, 1]
+        );
+
+        let errors = Assembler::new()
+            .aliases("HALT = GOTO", "course.cfg")
+            .unwrap_err();
+        assert_eq!(errors[0].severity, Severity::Error);
+        assert_eq!(
+            errors[0].span.as_ref().map(|span| span.file.as_str()),
+            Some("course.cfg")
+        );
+    }
+
+    #[test]
+    fn objects() {
+        let assembler = Assembler::new().path("main.patp");
+        let (object, warnings) = assembler
+            .assemble_object("EXTERN double\nJUMP double\nunused: STOP")
+            .unwrap();
+        assert_eq!(object.code, [0b1000_0000, 1]);
+        assert_eq!(object.references[0].symbol, "double");
+        //nothing can get to the STOP, as jumping to an external leaves the module
+        let names: Vec<_> = warnings.iter().map(|warning| warning.name).collect();
+        assert_eq!(names, [Some("unreachable"), Some("unused_label")]);
+
+        let errors = assembler.assemble_object("JUMP double").unwrap_err();
+        assert_eq!(errors[0].severity, Severity::Error);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("error: Unknown symbol double\n --> main.patp:1:6\n"));
+    }
+
+    #[test]
+    fn errors() {
+        let errors = Assembler::new()
+            .path("prog.patp")
+            .assemble("ADD 40\nJUMP nowhere")
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].message,
+            "Invalid operand: operand is out of range"
+        );
+        assert_eq!(
+            errors[1].span,
+            Some(Span {
+                file: "prog.patp".to_owned(),
+                line: 2,
+                column: 6,
+                len: 7
+            })
+        );
+        assert_eq!(errors[1].name, None);
+        assert!(errors[1]
+            .to_string()
+            .starts_with("error: Unknown symbol nowhere\n --> prog.patp:2:6\n"));
+    }
+}
@@ -4,12 +4,16 @@ use anyhow::Result;
 use thiserror::Error;
 
 use crate::instruction::Instruction;
+use crate::machine::RunOutcome;
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     memory: [u8; 32],
     z: bool,
     register: u8,
+    //saved states can be edited by hand, so make sure the pc still points into memory
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "address"))]
     pc: u8,
 }
 
@@ -23,6 +27,15 @@ pub enum CPUError {
 
     #[error("CPU has finished execution")]
     Stop(Cpu),
+
+    #[error("Program did not halt within {0} cycles. CPU state: \n{1}")]
+    StepLimit(usize, Cpu),
+
+    #[error("Program will never halt: it loops forever from address {0}. CPU state: \n{1}")]
+    InfiniteLoop(u8, Cpu),
+
+    #[error("Program paused: {0}. CPU state: \n{1}")]
+    Paused(RunOutcome, Cpu),
 }
 
 impl Cpu {
@@ -40,14 +53,21 @@ impl Cpu {
     //executes a single instruction
     //consumes self and returns Ok(new state), or returns finishing state wrapped in an error if a STOP is hit
     pub fn execute(mut self, instruction: Instruction) -> Result<Cpu, CPUError> {
+        if self.apply(instruction) {
+            Ok(self)
+        } else {
+            Err(CPUError::Stop(self))
+        }
+    }
+
+    //executes a single instruction in place, returning false if it was a STOP
+    pub(crate) fn apply(&mut self, instruction: Instruction) -> bool {
         match instruction {
             Instruction::Clear(0) => {
                 self.register = 0;
                 self.z = true;
             }
-            Instruction::Clear(_) => {
-                return Err(CPUError::Stop(self));
-            }
+            Instruction::Clear(_) => return false,
             Instruction::Inc => {
                 self.register = u8::wrapping_add(self.register, 1);
                 self.z = self.register == 0;
@@ -69,8 +89,68 @@ impl Cpu {
             Instruction::Load(op) => self.register = self.memory[op as usize],
             Instruction::Store(op) => self.memory[op as usize] = self.register,
         }
+        true
+    }
 
-        Ok(self)
+    pub fn pc(&self) -> u8 {
+        self.pc
+    }
+
+    pub fn memory(&self) -> &[u8; 32] {
+        &self.memory
+    }
+
+    pub fn register(&self) -> u8 {
+        self.register
+    }
+
+    pub fn z(&self) -> bool {
+        self.z
+    }
+
+    //the program counter is only 5 bits, so anything past 31 wraps around
+    pub fn set_pc(&mut self, pc: u8) {
+        self.pc = pc % 32;
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8; 32] {
+        &mut self.memory
+    }
+
+    pub fn set_register(&mut self, register: u8) {
+        self.register = register;
+    }
+
+    pub fn set_z(&mut self, z: bool) {
+        self.z = z;
+    }
+
+    //builders for setting up a starting state, like Cpu::new().with_register(3).with_pc(4)
+    //loading a program over the top of it still leaves everything past the end of the program alone
+    pub fn with_pc(mut self, pc: u8) -> Cpu {
+        self.set_pc(pc);
+        self
+    }
+
+    pub fn with_memory(mut self, memory: [u8; 32]) -> Cpu {
+        self.memory = memory;
+        self
+    }
+
+    pub fn with_register(mut self, register: u8) -> Cpu {
+        self.register = register;
+        self
+    }
+
+    pub fn with_z(mut self, z: bool) -> Cpu {
+        self.z = z;
+        self
+    }
+
+    //fetches and executes the next instruction
+    pub fn step(mut self) -> Result<Cpu, CPUError> {
+        let instruction = self.fetch();
+        self.execute(instruction)
     }
 
     //wrap at 5 bits (cant go past 31)
@@ -101,3 +181,17 @@ impl fmt::Display for Cpu {
         )
     }
 }
+
+#[cfg(feature = "serde")]
+fn address<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    use serde::Deserialize;
+    let address = u8::deserialize(deserializer)?;
+    if address < 32 {
+        Ok(address)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "program counter {} is outside of memory",
+            address
+        )))
+    }
+}
@@ -0,0 +1,385 @@
+use std::io::{self, BufRead, Write};
+
+use crate::cpu::{CPUError, Cpu};
+use crate::instruction::Instruction;
+use crate::machine::{Machine, RunOutcome, Watchpoint};
+use crate::parser::DebugInfo;
+
+//an interactive debugger, for stepping through a program one instruction at a time
+pub struct Debugger {
+    machine: Machine,
+    debug_info: DebugInfo,
+}
+
+//the reasons execution might pause, which are the same as the reasons a run stops
+pub type Event = RunOutcome;
+
+impl Debugger {
+    //load a program into a fresh cpu, keeping the debug info (which may be empty) to show where we are
+    pub fn new(program: &[u8], debug_info: DebugInfo) -> Result<Debugger, CPUError> {
+        Ok(Debugger {
+            machine: Machine::new(program)?,
+            debug_info,
+        })
+    }
+
+    //look up an address, either a number or a label from the symbol table
+    pub fn resolve(&self, location: &str) -> Option<u8> {
+        let address = match location.parse::<usize>() {
+            Ok(address) => address,
+            Err(_) => self.debug_info.symbol(location)?,
+        };
+        u8::try_from(address).ok().filter(|a| *a < 32)
+    }
+
+    pub fn add_breakpoint(&mut self, address: u8) {
+        self.machine.add_breakpoint(address);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.machine.add_watchpoint(watchpoint);
+    }
+
+    //removes any breakpoints or watchpoints on an address, returning whether there were any
+    pub fn delete(&mut self, address: u8) -> bool {
+        self.machine.delete(address)
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        self.machine.cpu()
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.machine.cycles()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.machine.is_halted()
+    }
+
+    //executes a single instruction, returning it along with the address it was fetched from
+    //returns None if the program has already halted
+    pub fn step(&mut self) -> Option<(u8, Instruction)> {
+        self.machine.step()
+    }
+
+    //keep stepping until the program hits a STOP, a breakpoint or a watchpoint
+    //programs that loop forever or run out of cycles stop too, so this always comes back
+    pub fn continue_execution(&mut self) -> Event {
+        self.machine.run().outcome
+    }
+
+    //the command loop, reading commands from input and writing everything to output
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        writeln!(output, "PATP debugger. Type 'help' for a list of commands.")?;
+        self.show_position(&mut output)?;
+
+        loop {
+            write!(output, "(patp) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                //an empty line steps, same as gdb
+                None | Some("s") | Some("step") => {
+                    let count = match words.next().map(str::parse::<usize>) {
+                        None => 1,
+                        Some(Ok(n)) => n,
+                        Some(Err(_)) => {
+                            writeln!(output, "Step count must be a positive number")?;
+                            continue;
+                        }
+                    };
+                    for _ in 0..count {
+                        if self.is_halted() {
+                            break;
+                        }
+                        let pc = self.cpu().pc();
+                        let instruction =
+                            Instruction::disassemble(self.cpu().memory()[pc as usize]);
+                        writeln!(output, "Executed {:>2}: {}", pc, instruction)?;
+                        //halting gets reported by show_position, but everything else should stop us early
+                        match self.machine.step_checked() {
+                            None | Some(Event::Halted) => (),
+                            Some(event) => {
+                                writeln!(output, "{}", event)?;
+                                break;
+                            }
+                        }
+                    }
+                    writeln!(output, "{}", self.cpu())?;
+                    self.show_position(&mut output)?;
+                }
+                Some("c") | Some("continue") => {
+                    let event = self.continue_execution();
+                    if event != Event::Halted {
+                        writeln!(output, "{}", event)?;
+                    }
+                    writeln!(output, "{}", self.cpu())?;
+                    self.show_position(&mut output)?;
+                }
+                Some("b") | Some("break") => match words.next() {
+                    None => self.show_breakpoints(&mut output)?,
+                    Some(location) => match self.resolve(location) {
+                        Some(address) => {
+                            self.add_breakpoint(address);
+                            writeln!(output, "Breakpoint set at {}", address)?;
+                        }
+                        None => writeln!(output, "Unknown address or label '{}'", location)?,
+                    },
+                },
+                Some("watch") => match (words.next(), words.next()) {
+                    (None, _) => self.show_breakpoints(&mut output)?,
+                    (Some(location), kind) => match (self.resolve(location), kind) {
+                        (None, _) => writeln!(output, "Unknown address or label '{}'", location)?,
+                        (Some(address), None) | (Some(address), Some("store")) => {
+                            self.add_watchpoint(Watchpoint::Store(address));
+                            writeln!(output, "Watching for stores to {}", address)?;
+                        }
+                        (Some(address), Some("change")) => {
+                            self.add_watchpoint(Watchpoint::Change(address));
+                            writeln!(output, "Watching for changes at {}", address)?;
+                        }
+                        (Some(_), Some(other)) => writeln!(
+                            output,
+                            "Unknown watchpoint type '{}', expected 'store' or 'change'",
+                            other
+                        )?,
+                    },
+                },
+                Some("d") | Some("delete") => match words.next().map(|l| (l, self.resolve(l))) {
+                    None => writeln!(output, "Expected an address or label to delete")?,
+                    Some((location, None)) => {
+                        writeln!(output, "Unknown address or label '{}'", location)?
+                    }
+                    Some((_, Some(address))) => {
+                        if self.delete(address) {
+                            writeln!(output, "Deleted breakpoints and watchpoints at {}", address)?
+                        } else {
+                            writeln!(output, "Nothing set at {}", address)?
+                        }
+                    }
+                },
+                Some("r") | Some("regs") => writeln!(output, "{}", self.cpu())?,
+                Some("m") | Some("mem") => self.show_memory(&mut output)?,
+                Some("w") | Some("where") => self.show_position(&mut output)?,
+                Some("h") | Some("help") => writeln!(output, "{}", HELP)?,
+                Some("q") | Some("quit") => break,
+                Some(other) => {
+                    writeln!(output, "Unknown command '{}'. Type 'help' for help.", other)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //print the next instruction to be executed, and the source line it came from if we know it
+    fn show_position(&self, output: &mut impl Write) -> io::Result<()> {
+        if self.is_halted() {
+            return writeln!(output, "Program halted after {} cycles", self.cycles());
+        }
+        let pc = self.cpu().pc();
+        let instruction = Instruction::disassemble(self.cpu().memory()[pc as usize]);
+        match self.debug_info.source_map.get(&pc) {
+            Some(line) => writeln!(
+                output,
+                "Next {:>2}: {:<10} (line {}: {})",
+                pc,
+                instruction.to_string(),
+                line.number,
+                line.text
+            ),
+            None => writeln!(output, "Next {:>2}: {}", pc, instruction),
+        }
+    }
+
+    fn show_breakpoints(&self, output: &mut impl Write) -> io::Result<()> {
+        if self.machine.breakpoints().next().is_none() && self.machine.watchpoints().is_empty() {
+            return writeln!(output, "No breakpoints or watchpoints set");
+        }
+        for address in self.machine.breakpoints() {
+            writeln!(output, "Breakpoint at {}", address)?;
+        }
+        for watchpoint in self.machine.watchpoints() {
+            writeln!(output, "Watchpoint on {}", watchpoint)?;
+        }
+        Ok(())
+    }
+
+    //dump memory as 4 rows of 8 bytes, in hex
+    fn show_memory(&self, output: &mut impl Write) -> io::Result<()> {
+        for (row, bytes) in self.cpu().memory().chunks(8).enumerate() {
+            write!(output, "{:>2}:", row * 8)?;
+            for byte in bytes {
+                write!(output, " {:02x}", byte)?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+}
+
+const HELP: &str = "Commands:
+  step [n], s [n]  execute the next n instructions (default 1, or just press enter)
+  continue, c      run until the program hits a STOP, breakpoint or watchpoint, or loops forever
+  break <loc>, b   set a breakpoint at an address or label (lists them with no argument)
+  watch <loc> [store|change]
+                   pause after any STORE to an address (default), or when its value changes
+  delete <loc>, d  remove any breakpoints and watchpoints at an address or label
+  regs, r          print the program counter, register, Z flag and memory
+  mem, m           print memory as a hex dump
+  where, w         show the next instruction and its source line
+  help, h          show this message
+  quit, q          exit the debugger";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::parser::parse_file_with_source;
+
+    const PROGRAM: &str = "CLEAR\n\n; count down from 2\nADD 2\nloop: DEC\nBNZ loop\nSTOP";
+
+    fn load(program: &str) -> Debugger {
+        let (instructions, debug_info) = parse_file_with_source(program).unwrap();
+        let binary = crate::assemble_instructions(&instructions).unwrap();
+        Debugger::new(&binary, debug_info).unwrap()
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = load(PROGRAM);
+        assert_eq!(debugger.step(), Some((0, Instruction::Clear(0))));
+        assert_eq!(debugger.step(), Some((1, Instruction::Add(2))));
+        assert_eq!(debugger.step(), Some((2, Instruction::Dec)));
+        assert_eq!(debugger.step(), Some((3, Instruction::Bnz(2))));
+        assert_eq!(debugger.cpu().pc(), 2);
+
+        assert_eq!(debugger.continue_execution(), Event::Halted);
+        assert!(debugger.is_halted());
+        assert_eq!(debugger.cycles(), 7);
+        assert_eq!(debugger.step(), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let mut debugger = load(PROGRAM);
+        let mut output = Vec::new();
+        debugger
+            .run("step 2\n\nbogus\nc\n".as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        //real source lines, not instruction indices
+        assert!(output.contains("Next  0: CLEAR      (line 1: CLEAR)"));
+        assert!(output.contains("Next  2: DEC        (line 5: loop: DEC)"));
+        assert!(output.contains("Executed  1: ADD 2"));
+        assert!(output.contains("Unknown command 'bogus'"));
+        assert!(output.contains("Program halted after 7 cycles"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = load(PROGRAM);
+        let address = debugger.resolve("loop").unwrap();
+        assert_eq!(address, 2);
+        assert_eq!(debugger.resolve("31"), Some(31));
+        assert_eq!(debugger.resolve("32"), None);
+        assert_eq!(debugger.resolve("nowhere"), None);
+
+        //loop body runs twice, then we fall through to the STOP
+        debugger.add_breakpoint(address);
+        assert_eq!(debugger.continue_execution(), Event::Breakpoint(2));
+        assert_eq!(debugger.cycles(), 2);
+        assert_eq!(debugger.continue_execution(), Event::Breakpoint(2));
+        assert_eq!(debugger.cycles(), 4);
+        assert_eq!(debugger.continue_execution(), Event::Halted);
+
+        assert!(debugger.delete(address));
+        assert!(!debugger.delete(address));
+    }
+
+    //labels are found by whatever spelling the program was assembled with
+    #[test]
+    fn test_ignore_case() {
+        let source = "Loop: DEC\nBNZ loop\nSTOP";
+        let program = Assembler::new()
+            .case_insensitive(true)
+            .assemble(source)
+            .unwrap();
+        let debugger = Debugger::new(&program.bytes, program.debug_info().clone()).unwrap();
+        assert_eq!(debugger.resolve("Loop"), Some(0));
+        assert_eq!(debugger.resolve("loop"), Some(0));
+        assert_eq!(debugger.resolve("LOOP"), Some(0));
+
+        //but only if it was assembled that way
+        let debugger = load("Loop: DEC\nBNZ Loop\nSTOP");
+        assert_eq!(debugger.resolve("Loop"), Some(0));
+        assert_eq!(debugger.resolve("loop"), None);
+    }
+
+    //the self-modifying program from examples/inc.patp, which stores a STOP at address 5
+    #[test]
+    fn test_watchpoints() {
+        let program = "CLEAR\nINC\nINC\nDEC\nSTORE 5";
+
+        let mut debugger = load(program);
+        debugger.add_watchpoint(Watchpoint::Change(5));
+        assert_eq!(
+            debugger.continue_execution(),
+            Event::Watchpoint {
+                watchpoint: Watchpoint::Change(5),
+                old: 0,
+                new: 1
+            }
+        );
+        assert_eq!(debugger.cpu().pc(), 5);
+        assert_eq!(debugger.continue_execution(), Event::Halted);
+
+        //storing the same value again still triggers a store watchpoint, but not a change one
+        let mut debugger = load("CLEAR\nSTORE 31\nSTORE 31\nSTOP");
+        debugger.add_watchpoint(Watchpoint::Change(31));
+        debugger.add_watchpoint(Watchpoint::Store(31));
+        let event = Event::Watchpoint {
+            watchpoint: Watchpoint::Store(31),
+            old: 0,
+            new: 0,
+        };
+        assert_eq!(debugger.continue_execution(), event);
+        assert_eq!(debugger.continue_execution(), event);
+        assert_eq!(debugger.continue_execution(), Event::Halted);
+    }
+
+    //continuing a program that never stops comes back rather than hanging
+    #[test]
+    fn test_loops() {
+        let mut debugger = load("CLEAR\nself: JUMP self");
+        assert_eq!(debugger.continue_execution(), Event::LoopDetected(1));
+        assert!(!debugger.is_halted());
+        assert_eq!(debugger.continue_execution(), Event::LoopDetected(1));
+    }
+
+    #[test]
+    fn test_break_session() {
+        let mut debugger = load(PROGRAM);
+        let mut output = Vec::new();
+        debugger
+            .run(
+                "break loop\nwatch 30 sometimes\nbreak\nc\ndelete loop\nc\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Breakpoint set at 2"));
+        assert!(output.contains("Unknown watchpoint type 'sometimes'"));
+        assert!(output.contains("Breakpoint at 2\nProgram Counter: 2"));
+        assert!(output.contains("Deleted breakpoints and watchpoints at 2"));
+        assert!(output.contains("Program halted after 7 cycles"));
+    }
+}
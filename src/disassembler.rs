@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+
+use crate::instruction::Instruction;
+
+//turns a binary back into assembly that parse_file will accept
+//JUMP and BNZ targets get labels, and any bytes that are only ever loaded or stored are written as data
+pub fn disassemble(program: &[u8]) -> String {
+    let instructions: Vec<Instruction> = program
+        .iter()
+        .map(|byte| Instruction::disassemble(*byte))
+        .collect();
+
+    //branch targets inside the program get labels, anything outside is left as a number
+    let labels: BTreeSet<u8> = instructions
+        .iter()
+        .filter_map(|instruction| match *instruction {
+            Instruction::Jump(target) | Instruction::Bnz(target) => Some(target),
+            _ => None,
+        })
+        .filter(|target| (*target as usize) < program.len())
+        .collect();
+
+    let reachable = reachable(&instructions);
+    let data: BTreeSet<u8> = instructions
+        .iter()
+        .filter_map(|instruction| match *instruction {
+            Instruction::Load(address) | Instruction::Store(address) => Some(address),
+            _ => None,
+        })
+        .filter(|address| (*address as usize) < program.len() && !reachable.contains(address))
+        .collect();
+
+    let lines = program.iter().zip(&instructions).enumerate();
+    lines
+        .map(|(address, (byte, instruction))| {
+            let address = address as u8;
+
+            let definition = if labels.contains(&address) {
+                format!("{}:", label(address))
+            } else {
+                String::new()
+            };
+
+            //STOP is any CLEAR with a non-zero operand but is only ever written back as 1,
+            //so anything else has to be written as a raw byte too
+            let text = match *instruction {
+                _ if data.contains(&address) => format!("DB {}", byte),
+                Instruction::Clear(op) if op > 1 => format!("DB {}", byte),
+                Instruction::Jump(target) if labels.contains(&target) => {
+                    format!("JUMP {}", label(target))
+                }
+                Instruction::Bnz(target) if labels.contains(&target) => {
+                    format!("BNZ {}", label(target))
+                }
+                _ => instruction.to_string(),
+            };
+
+            let mut comment = format!("{:>2}: 0x{:02x}", address, byte);
+            if data.contains(&address) {
+                comment.push_str(" data");
+            }
+
+            format!("{:<6}{:<12}; {}\n", definition, text, comment)
+        })
+        .collect()
+}
+
+//synthesised label names, just the address so they're easy to follow
+fn label(address: u8) -> String {
+    format!("L{}", address)
+}
+
+//follow control flow from the start of the program to find every address that could be executed
+fn reachable(instructions: &[Instruction]) -> BTreeSet<u8> {
+    let mut reachable = BTreeSet::new();
+    let mut pending = vec![0u8];
+
+    while let Some(address) = pending.pop() {
+        if address as usize >= instructions.len() || !reachable.insert(address) {
+            continue;
+        }
+        let next = (address + 1) % 32;
+        match instructions[address as usize] {
+            Instruction::Clear(op) if op != 0 => (),
+            Instruction::Jump(target) => pending.push(target),
+            Instruction::Bnz(target) => pending.extend([target, next]),
+            _ => pending.push(next),
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assemble_instructions, parse_file};
+
+    fn assemble(program: &str) -> Vec<u8> {
+        assemble_instructions(&parse_file(program).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let binary = assemble("CLEAR\nloop: LOAD 6\nDEC\nSTORE 6\nBNZ loop\nSTOP\nx: DB 67, 15");
+        let listing = disassemble(&binary);
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines[0], "      CLEAR       ;  0: 0x00");
+        assert_eq!(lines[1], "L1:   LOAD 6      ;  1: 0xc6");
+        assert_eq!(lines[4], "      BNZ L1      ;  4: 0xa1");
+        //this is never executed, only loaded and stored
+        assert_eq!(lines[6], "      DB 67       ;  6: 0x43 data");
+        //this is never touched at all, but isn't an instruction we can write
+        assert_eq!(lines[7], "      DB 15       ;  7: 0x0f");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let programs = [
+            include_str!("../examples/add.patp"),
+            include_str!("../examples/inc.patp"),
+            include_str!("../examples/loop.patp"),
+            "STOP\nCLEAR\nSTORE 31\nx: DEC\nBNZ x\nJUMP x",
+            "LOAD y\nSTOP\ny: DB 3, 2, 1\nDS 4\nz: .byte 255",
+            //branches that go off the end of the program can only be numbers
+            "CLEAR\nBNZ 20\nJUMP 31",
+        ];
+        for program in programs {
+            let binary = assemble(program);
+            assert_eq!(assemble(&disassemble(&binary)), binary);
+        }
+
+        //a full 32 bytes should still fit
+        let binary: Vec<u8> = (0..32).map(|i| 0b0010_0000 | (i % 2) << 6).collect();
+        assert_eq!(assemble(&disassemble(&binary)), binary);
+    }
+}
@@ -0,0 +1,458 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+//the formats an assembled program can be written out as, and read back in from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    //just the bytes, exactly as they're loaded into memory
+    Raw,
+    //text, one byte per line as two hex digits
+    Hex,
+    //text, one byte per line as eight binary digits
+    Bin,
+    //intel hex records, which most eprom programmers and simulators understand
+    IntelHex,
+    //the same as hex and bin, with a comment saying what they're for, to load with $readmemh/$readmemb in verilog
+    Readmemh,
+    Readmemb,
+    //the "v2.0 raw" image that logisim-evolution loads into a ROM or RAM
+    Logisim,
+}
+
+//things that can be wrong with a file that's meant to be in one of the formats
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("Line {0}: {1} is not a valid byte")]
+    InvalidValue(usize, String),
+
+    #[error("Line {0}: address {1} is outside of memory")]
+    AddressOutOfRange(usize, usize),
+
+    #[error("Line {0}: not a valid Intel HEX record")]
+    InvalidRecord(usize),
+
+    #[error("Line {0}: checksum is {1:02X} but should be {2:02X}")]
+    Checksum(usize, u8, u8),
+
+    #[error("Line {0}: record type {1:02X} isn't supported")]
+    UnsupportedRecord(usize, u8),
+
+    #[error("Logisim images have to start with v2.0 raw")]
+    MissingHeader,
+}
+
+impl OutputFormat {
+    //turns a program into the contents of a file in this format
+    pub fn encode(&self, program: &[u8]) -> Vec<u8> {
+        let lines = |radix_format: fn(&u8) -> String| -> String {
+            program.iter().map(|b| radix_format(b) + "\n").collect()
+        };
+        let text = match self {
+            OutputFormat::Raw => return program.to_vec(),
+            OutputFormat::Hex => lines(|b| format!("{:02x}", b)),
+            OutputFormat::Bin => lines(|b| format!("{:08b}", b)),
+            OutputFormat::Readmemh => {
+                "// PATP program, load with $readmemh\n".to_owned()
+                    + &lines(|b| format!("{:02x}", b))
+            }
+            OutputFormat::Readmemb => {
+                "// PATP program, load with $readmemb\n".to_owned()
+                    + &lines(|b| format!("{:08b}", b))
+            }
+            OutputFormat::IntelHex => intel_hex(program),
+            OutputFormat::Logisim => {
+                let mut image = "v2.0 raw\n".to_owned();
+                for row in program.chunks(16) {
+                    let row: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+                    image.push_str(&row.join(" "));
+                    image.push('\n');
+                }
+                image
+            }
+        };
+        text.into_bytes()
+    }
+
+    //reads a program back from a file in this format
+    pub fn decode(&self, contents: &[u8]) -> Result<Vec<u8>, FormatError> {
+        //none of the text formats can have anything that isn't ascii in them, so nothing that matters is lost
+        let text = String::from_utf8_lossy(contents);
+        match self {
+            OutputFormat::Raw => Ok(contents.to_vec()),
+            OutputFormat::Hex | OutputFormat::Readmemh => read_memory(&text, 16),
+            OutputFormat::Bin | OutputFormat::Readmemb => read_memory(&text, 2),
+            OutputFormat::IntelHex => read_intel_hex(&text),
+            OutputFormat::Logisim => read_logisim(&text),
+        }
+    }
+
+    //works out which format a file is in from what's in it
+    //anything that doesn't look like one of the text formats is taken as raw bytes
+    pub fn detect(contents: &[u8]) -> OutputFormat {
+        let text = match std::str::from_utf8(contents) {
+            Ok(text) => text,
+            Err(_) => return OutputFormat::Raw,
+        };
+        if text.trim_start().starts_with("v2.0 raw") {
+            return OutputFormat::Logisim;
+        }
+
+        //the other text formats are only guessed when every line ends in a newline, as assemble writes them
+        //raw programs are bytes that can easily look like text (ADD 1 is an A, and INC is a space),
+        //but they can only end in a newline if they end in a DB 10
+        if !text.ends_with('\n') {
+            return OutputFormat::Raw;
+        }
+
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.clone().next().is_some()
+            && lines.all(|line| {
+                line.len() > 1
+                    && line.starts_with(':')
+                    && line[1..].chars().all(|c| c.is_ascii_hexdigit())
+            })
+        {
+            return OutputFormat::IntelHex;
+        }
+
+        //hex and bin are told apart by how many digits there are in each byte
+        //every line has to be exactly one byte written out in full, or a readmem @address followed by bytes
+        let lines: Vec<&str> = text
+            .lines()
+            .filter(|line| !line.starts_with("//"))
+            .collect();
+        let matches = |digits: usize, radix: u32| {
+            let value =
+                |value: &str| value.len() == digits && value.chars().all(|c| c.is_digit(radix));
+            let address = |address: &str| {
+                address.len() > 1
+                    && address.starts_with('@')
+                    && address[1..].chars().all(|c| c.is_ascii_hexdigit())
+            };
+            //how many bytes each line has, if it's a line that could be in this format
+            let bytes = |line: &&str| {
+                if line.starts_with('@') {
+                    let mut values = without_comment(line, "//").split_whitespace();
+                    let address = values.next().is_some_and(address);
+                    let values: Vec<&str> = values.collect();
+                    (address && values.iter().all(|v| value(v))).then_some(values.len())
+                } else {
+                    value(line).then_some(1)
+                }
+            };
+            let counts: Option<Vec<usize>> = lines.iter().map(bytes).collect();
+            counts.is_some_and(|counts| counts.iter().sum::<usize>() > 0)
+        };
+        if matches(2, 16) {
+            OutputFormat::Hex
+        } else if matches(8, 2) {
+            OutputFormat::Bin
+        } else {
+            OutputFormat::Raw
+        }
+    }
+}
+
+//reads a program from a file in whatever format it looks like it's in
+pub fn read_program(contents: &[u8]) -> Result<Vec<u8>, FormatError> {
+    OutputFormat::detect(contents).decode(contents)
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(OutputFormat::Raw),
+            "hex" => Ok(OutputFormat::Hex),
+            "bin" => Ok(OutputFormat::Bin),
+            "ihex" => Ok(OutputFormat::IntelHex),
+            "readmemh" => Ok(OutputFormat::Readmemh),
+            "readmemb" => Ok(OutputFormat::Readmemb),
+            "logisim" => Ok(OutputFormat::Logisim),
+            _ => Err(format!(
+                "Unknown output format '{}', expected one of raw, hex, bin, ihex, readmemh, readmemb, logisim",
+                s
+            )),
+        }
+    }
+}
+
+//memory as it's read in from a file, which can put bytes anywhere in any order
+//zeros past the end are fine, so images padded out to a bigger memory still load
+#[derive(Default)]
+struct Image {
+    memory: Vec<u8>,
+}
+
+impl Image {
+    fn put(&mut self, line: usize, address: usize, value: u8) -> Result<(), FormatError> {
+        if address >= 32 {
+            return match value {
+                0 => Ok(()),
+                _ => Err(FormatError::AddressOutOfRange(line, address)),
+            };
+        }
+        if self.memory.len() <= address {
+            self.memory.resize(address + 1, 0);
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+}
+
+fn without_comment<'a>(line: &'a str, comment: &str) -> &'a str {
+    line.split(comment).next().unwrap_or(line)
+}
+
+//one value after another in the given radix, with // comments, and @address to move to another address (in hex)
+fn read_memory(text: &str, radix: u32) -> Result<Vec<u8>, FormatError> {
+    let mut image = Image::default();
+    let mut address = 0;
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        for value in without_comment(line, "//").split_whitespace() {
+            let invalid = || FormatError::InvalidValue(number, value.to_owned());
+            if let Some(target) = value.strip_prefix('@') {
+                address = usize::from_str_radix(target, 16).map_err(|_| invalid())?;
+                continue;
+            }
+            image.put(
+                number,
+                address,
+                u8::from_str_radix(value, radix).map_err(|_| invalid())?,
+            )?;
+            address += 1;
+        }
+    }
+    Ok(image.memory)
+}
+
+//:LLAAAATTDD..CC, for length, address, record type, data, and a checksum so the bytes all add up to 0
+fn intel_hex(program: &[u8]) -> String {
+    let mut records = String::new();
+    for (i, data) in program.chunks(16).enumerate() {
+        let address = (i * 16) as u16;
+        let mut record = vec![data.len() as u8];
+        record.extend(address.to_be_bytes());
+        record.push(0);
+        record.extend(data);
+        let checksum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        record.push(checksum.wrapping_neg());
+
+        records.push(':');
+        for byte in record {
+            write!(records, "{:02X}", byte).unwrap();
+        }
+        records.push('\n');
+    }
+    //end of file
+    records.push_str(":00000001FF\n");
+    records
+}
+
+fn read_intel_hex(text: &str) -> Result<Vec<u8>, FormatError> {
+    let mut image = Image::default();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = FormatError::InvalidRecord(number);
+        let digits = line.strip_prefix(':').ok_or_else(|| invalid.clone())?;
+        if digits.len() % 2 != 0 || !digits.is_ascii() {
+            return Err(invalid);
+        }
+        let record = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid.clone())?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(invalid);
+        }
+
+        let (checksum, body) = record.split_last().unwrap();
+        let expected = body
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            .wrapping_neg();
+        if *checksum != expected {
+            return Err(FormatError::Checksum(number, *checksum, expected));
+        }
+
+        let address = u16::from_be_bytes([body[1], body[2]]) as usize;
+        match body[3] {
+            0 => {
+                for (i, value) in body[4..].iter().enumerate() {
+                    image.put(number, address + i, *value)?;
+                }
+            }
+            1 => break,
+            other => return Err(FormatError::UnsupportedRecord(number, other)),
+        }
+    }
+    Ok(image.memory)
+}
+
+//v2.0 raw, then values in hex separated by whitespace, where n*value is value n times, and # starts a comment
+fn read_logisim(text: &str) -> Result<Vec<u8>, FormatError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "v2.0 raw" => (),
+        _ => return Err(FormatError::MissingHeader),
+    }
+
+    let mut image = Image::default();
+    let mut address = 0;
+    for (number, line) in lines {
+        let number = number + 1;
+        for value in without_comment(line, "#").split_whitespace() {
+            let invalid = || FormatError::InvalidValue(number, value.to_owned());
+            let (count, byte) = match value.split_once('*') {
+                Some((count, byte)) => (count.parse().map_err(|_| invalid())?, byte),
+                None => (1, value),
+            };
+            let byte = u8::from_str_radix(byte, 16).map_err(|_| invalid())?;
+            for _ in 0..count {
+                image.put(number, address, byte)?;
+                address += 1;
+                //anything more is just zeros padding out a bigger memory, so stop counting them
+                if address > 32 && byte == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(image.memory)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAM: [u8; 5] = [0b0000_0000, 0b0100_1000, 0b0110_0000, 0b1010_0001, 1];
+
+    const FORMATS: [OutputFormat; 7] = [
+        OutputFormat::Raw,
+        OutputFormat::Hex,
+        OutputFormat::Bin,
+        OutputFormat::IntelHex,
+        OutputFormat::Readmemh,
+        OutputFormat::Readmemb,
+        OutputFormat::Logisim,
+    ];
+
+    #[test]
+    fn encode() {
+        let text = |format: OutputFormat| String::from_utf8(format.encode(&PROGRAM)).unwrap();
+        assert_eq!(OutputFormat::Raw.encode(&PROGRAM), PROGRAM);
+        assert_eq!(text(OutputFormat::Hex), "00\n48\n60\na1\n01\n");
+        assert_eq!(
+            text(OutputFormat::Bin),
+            "00000000\n01001000\n01100000\n10100001\n00000001\n"
+        );
+        assert_eq!(
+            text(OutputFormat::Readmemh),
+            "// PATP program, load with $readmemh\n00\n48\n60\na1\n01\n"
+        );
+        assert_eq!(
+            text(OutputFormat::IntelHex),
+            ":05000000004860A101B1\n:00000001FF\n"
+        );
+        assert_eq!(text(OutputFormat::Logisim), "v2.0 raw\n00 48 60 a1 01\n");
+
+        //intel hex records hold 16 bytes at most
+        let long = String::from_utf8(OutputFormat::IntelHex.encode(&[0x20; 20])).unwrap();
+        assert_eq!(
+            long.lines().map(|l| &l[..9]).collect::<Vec<_>>(),
+            [":10000000", ":04001000", ":00000001"]
+        );
+    }
+
+    //everything written out in one format reads back in as the same program, without having to say which format it is
+    #[test]
+    fn round_trip() {
+        for format in FORMATS {
+            let encoded = format.encode(&PROGRAM);
+            let expected = match format {
+                //the comments don't change anything about how they're read
+                OutputFormat::Readmemh => OutputFormat::Hex,
+                OutputFormat::Readmemb => OutputFormat::Bin,
+                format => format,
+            };
+            assert_eq!(OutputFormat::detect(&encoded), expected, "{:?}", format);
+            assert_eq!(read_program(&encoded), Ok(PROGRAM.to_vec()), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn decode() {
+        //readmem addresses, and zeros padding out a bigger memory
+        assert_eq!(
+            OutputFormat::Readmemh.decode(b"// comment\n@02 20 // INC\n1 @1f 0 00 00\n"),
+            Ok([vec![0, 0, 0x20, 1], vec![0; 27], vec![0]].concat())
+        );
+        assert_eq!(
+            read_program(b"v2.0 raw\n# a comment\n2*20 3*0 01\n250*0\n"),
+            Ok([vec![0x20, 0x20, 0, 0, 0, 1], vec![0; 26]].concat())
+        );
+        assert_eq!(
+            read_program(b"v2.0 raw\n40*1\n"),
+            Err(FormatError::AddressOutOfRange(2, 32))
+        );
+        assert_eq!(
+            read_program(b":05000000004860A101B2\n"),
+            Err(FormatError::Checksum(1, 0xB2, 0xB1))
+        );
+        assert_eq!(
+            read_program(b":020000040000FA\n"),
+            Err(FormatError::UnsupportedRecord(1, 4))
+        );
+        assert_eq!(
+            OutputFormat::Hex.decode(b"00\n4g\n"),
+            Err(FormatError::InvalidValue(2, "4g".to_owned()))
+        );
+        assert_eq!(
+            OutputFormat::Logisim.decode(b"00 48\n"),
+            Err(FormatError::MissingHeader)
+        );
+
+        //anything that isn't clearly text is raw, even if some of it could be
+        assert_eq!(OutputFormat::detect(&[0xff, 0x20]), OutputFormat::Raw);
+        assert_eq!(OutputFormat::detect(b"  \n"), OutputFormat::Raw);
+        assert_eq!(OutputFormat::detect(b":xyz"), OutputFormat::Raw);
+        assert_eq!(OutputFormat::detect(b"ADD"), OutputFormat::Raw);
+
+        //ADD 1, ADD 2, INC, DB 10 is "AB \n", which isn't a byte on its own line so isn't taken as hex
+        let program = [0x41, 0x42, 0x20, 0x0a];
+        assert_eq!(OutputFormat::detect(&program), OutputFormat::Raw);
+        assert_eq!(read_program(&program), Ok(program.to_vec()));
+        assert_eq!(OutputFormat::detect(b"4\n20\n"), OutputFormat::Raw);
+        assert_eq!(OutputFormat::detect(b"1\n00000001\n"), OutputFormat::Raw);
+
+        //ADD 1, ADD 2 is "AB", and without a newline on the end it can't be a line of hex
+        assert_eq!(OutputFormat::detect(b"AB"), OutputFormat::Raw);
+        assert_eq!(OutputFormat::detect(b":00000001FF"), OutputFormat::Raw);
+        //every byte that looks like a hex digit, which nothing but a DB 10 can put a newline after
+        let digits: Vec<u8> = (b'0'..=b'9')
+            .chain(b'A'..=b'F')
+            .chain(b'a'..=b'f')
+            .collect();
+        for program in digits.chunks(2).chain([digits.as_slice()]) {
+            assert_eq!(read_program(program), Ok(program.to_vec()), "{:?}", program);
+        }
+
+        //readmem files written by hand, with addresses on their own or in front of bytes
+        assert_eq!(
+            OutputFormat::detect(b"// testbench\n@00\n20\n01\n@1e 0a 0b // data\n"),
+            OutputFormat::Hex
+        );
+        assert_eq!(read_program(b"@02 20\n01\n"), Ok(vec![0, 0, 0x20, 1]));
+        assert_eq!(OutputFormat::detect(b"@1f\n00000001\n"), OutputFormat::Bin);
+        assert_eq!(OutputFormat::detect(b"@00\n"), OutputFormat::Raw);
+    }
+}
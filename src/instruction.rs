@@ -1,8 +1,10 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use crate::cpu::CPUError;
 //the type to represent instructions
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Clear(u8), //000
     Inc,       //001
@@ -14,6 +16,91 @@ pub enum Instruction {
     Store(u8), //111
 }
 
+//an instruction mnemonic: whether it takes an operand, and how to build the instruction from it
+pub(crate) struct Mnemonic {
+    pub(crate) name: &'static str,
+    pub(crate) operand: bool,
+    pub(crate) instruction: fn(u8) -> Instruction,
+}
+
+//every mnemonic the assembler knows, which parsing and assembling a line go by, as does reading an instruction back from text
+//some instructions have more than one name, as different courses have called them different things
+pub(crate) const MNEMONICS: &[Mnemonic] = &[
+    Mnemonic {
+        name: "CLEAR",
+        operand: false,
+        instruction: |_| Instruction::Clear(0),
+    },
+    //STOP is a CLEAR with a non-zero operand
+    Mnemonic {
+        name: "STOP",
+        operand: false,
+        instruction: |_| Instruction::Clear(1),
+    },
+    Mnemonic {
+        name: "INC",
+        operand: false,
+        instruction: |_| Instruction::Inc,
+    },
+    Mnemonic {
+        name: "ADD",
+        operand: true,
+        instruction: Instruction::Add,
+    },
+    Mnemonic {
+        name: "DEC",
+        operand: false,
+        instruction: |_| Instruction::Dec,
+    },
+    Mnemonic {
+        name: "JUMP",
+        operand: true,
+        instruction: Instruction::Jump,
+    },
+    Mnemonic {
+        name: "JMP",
+        operand: true,
+        instruction: Instruction::Jump,
+    },
+    Mnemonic {
+        name: "BNZ",
+        operand: true,
+        instruction: Instruction::Bnz,
+    },
+    Mnemonic {
+        name: "BUZ",
+        operand: true,
+        instruction: Instruction::Bnz,
+    },
+    Mnemonic {
+        name: "BZC",
+        operand: true,
+        instruction: Instruction::Bnz,
+    },
+    Mnemonic {
+        name: "BNE",
+        operand: true,
+        instruction: Instruction::Bnz,
+    },
+    Mnemonic {
+        name: "LOAD",
+        operand: true,
+        instruction: Instruction::Load,
+    },
+    Mnemonic {
+        name: "STORE",
+        operand: true,
+        instruction: Instruction::Store,
+    },
+];
+
+//looks up one of the built in mnemonics, whatever case it's written in
+pub(crate) fn mnemonic(name: &str) -> Option<&'static Mnemonic> {
+    MNEMONICS
+        .iter()
+        .find(|mnemonic| mnemonic.name.eq_ignore_ascii_case(name))
+}
+
 //for displaying errors
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -24,12 +111,39 @@ impl Display for Instruction {
             Instruction::Add(x) => write!(f, "ADD {}", x),
             Instruction::Dec => write!(f, "DEC"),
             Instruction::Jump(x) => write!(f, "JUMP {}", x),
-            Instruction::Bnz(x) => write!(f, "BNX {}", x),
+            Instruction::Bnz(x) => write!(f, "BNZ {}", x),
             Instruction::Load(x) => write!(f, "LOAD {}", x),
             Instruction::Store(x) => write!(f, "STORE {}", x),
         }
     }
 }
+//reads back what Display writes, which is how traces write instructions, going by the same mnemonics as the assembler
+//a STOP is always read back as CLEAR 1, as the text doesn't say what its operand was
+impl FromStr for Instruction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not an instruction", s);
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or_else(invalid)?;
+        let operand = match parts.next() {
+            Some(operand) => Some(operand.parse::<u8>().map_err(|_| invalid())?),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let instruction = match (mnemonic(name), operand) {
+            (Some(mnemonic), Some(op)) if mnemonic.operand => (mnemonic.instruction)(op),
+            (Some(mnemonic), None) if !mnemonic.operand => (mnemonic.instruction)(0),
+            _ => return Err(invalid()),
+        };
+        //make sure the operand fits
+        instruction.assemble().map_err(|_| invalid())?;
+        Ok(instruction)
+    }
+}
+
 //methods to convert from/to our enum format
 //STOP is represented internally as a CLEAR with a non-zero operand
 impl Instruction {
@@ -98,4 +212,20 @@ mod test {
             Err(CPUError::AssemblyError(Instruction::Add(68)))
         );
     }
+
+    //and read back from how they're displayed
+    #[test]
+    fn test_from_str() {
+        for byte in [0, 1, 0b0010_0000, 0b0100_0011, 0b1001_1111, 0b1110_0001] {
+            let instruction = Instruction::disassemble(byte);
+            assert_eq!(instruction.to_string().parse(), Ok(instruction));
+        }
+        assert_eq!("stop".parse(), Ok(Instruction::Clear(1)));
+        //any other name the assembler knows works too
+        assert_eq!("jmp 3".parse(), Ok(Instruction::Jump(3)));
+        assert!("ADD 32".parse::<Instruction>().is_err());
+        assert!("INC 1".parse::<Instruction>().is_err());
+        assert!("JUMP".parse::<Instruction>().is_err());
+        assert!("STORE 1 2".parse::<Instruction>().is_err());
+    }
 }
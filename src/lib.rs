@@ -1,58 +1,310 @@
 #![allow(clippy::enum_variant_names)]
 
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 //expose some bits that may be useful
-pub use cpu::Cpu;
+pub use assembler::{Assembler, Diagnostic, Program, Severity};
+pub use cpu::{CPUError, Cpu};
+pub use debugger::{Debugger, Event};
+pub use disassembler::disassemble;
+pub use format::{read_program, FormatError, OutputFormat};
 pub use instruction::Instruction;
-pub use parser::parse_file;
+pub use listing::listing;
+pub use machine::{Machine, Run, RunOutcome, Snapshot, Watchpoint, DEFAULT_MAX_CYCLES};
+pub use object::{
+    link, Export, Field, LinkError, MemoryMap, Object, ObjectError, Reference, Relocation,
+};
+pub use parser::{
+    parse_file, parse_file_from, parse_file_with_options, parse_file_with_source, parse_object,
+    render_errors, DebugInfo, ParseError, ParseOptions, SourceLine, SourceMap, Span, Warning,
+};
+#[cfg(feature = "serde")]
+pub use state::{StateError, StateFormat};
+pub use trace::{TraceEntry, TraceFormat};
 
+mod assembler;
 mod cpu;
+mod debugger;
+mod disassembler;
+mod format;
 mod instruction;
+mod listing;
+mod machine;
+mod object;
 mod parser;
+#[cfg(feature = "serde")]
+mod state;
+mod trace;
 
-//reads a text file from disk, assembles the instructions and writes a binary file to disk with the assembled program
-pub fn assemble_file(path: impl AsRef<Path>) -> Result<()> {
+//reads a text file, assembles the instructions and writes out the assembled program in the given format
+//the output goes next to the source with the extension taken off unless told otherwise, or to stdout if the source came from stdin
+//optionally writes a listing file alongside it too
+pub fn assemble_file(
+    path: impl AsRef<Path>,
+    output: Option<&Path>,
+    format: OutputFormat,
+    write_listing: bool,
+    options: &ParseOptions,
+) -> Result<()> {
     let path = path.as_ref();
 
-    let file = fs::read_to_string(path)?;
+    let file = read_text(path)?;
 
-    let binary = assemble_instructions(&parse_file(&file)?)?;
+    let program = parse_path(&file, path, options)?;
 
-    //todo - come up with something to return this error instead of unwrapping
-    let outfile = path.file_stem().unwrap();
+    let output = output_path(path, output, "")?;
+    write(&output, &format.encode(&program.bytes))?;
 
-    fs::write(outfile, &binary)?;
+    if write_listing {
+        if is_stdio(&output) {
+            anyhow::bail!("The listing is written next to the binary, so it can't go to stdout");
+        }
+        fs::write(
+            output.with_extension("lst"),
+            listing(&file, &program.bytes, program.debug_info()),
+        )?;
+    }
     Ok(())
 }
 
-//executes a binary file on disk, printing the final state to stdout
-pub fn execute_file(path: impl AsRef<Path>) -> Result<()> {
-    let file = fs::read(path)?;
-    let final_state = execute_program(&file)?;
-    println!("Final CPU State: \n{}", final_state);
+//reads a text file and assembles it as one module of a program, writing out an object file to be linked with others
+//the object goes next to the source with a .o extension unless told otherwise
+pub fn assemble_object_file(
+    path: impl AsRef<Path>,
+    output: Option<&Path>,
+    options: &ParseOptions,
+) -> Result<()> {
+    let path = path.as_ref();
+    let file = read_text(path)?;
+
+    let (object, warnings) = assembler(path, options)
+        .assemble_object(&file)
+        .map_err(diagnostics_error)?;
+    print_warnings(&warnings);
+
+    write(
+        &output_path(path, output, "o")?,
+        object.to_string().as_bytes(),
+    )?;
     Ok(())
 }
 
-//reads a text file from disk, assembles it, and then runs it, printing the final state to stdout
-pub fn run_file(path: impl AsRef<Path>) -> Result<()> {
-    let file = fs::read_to_string(path)?;
+//links object files into a program, in the order given, and writes it out in the given format
+//the output goes next to the first object with the extension taken off unless told otherwise
+pub fn link_files(paths: &[PathBuf], output: Option<&Path>, format: OutputFormat) -> Result<()> {
+    let mut modules = Vec::new();
+    for path in paths {
+        let object = read_text(path)?
+            .parse()
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        modules.push((path.display().to_string(), object));
+    }
+
+    let binary = link(&modules).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        anyhow::anyhow!(errors.join("\n"))
+    })?;
+
+    let first = paths
+        .first()
+        .map(PathBuf::as_path)
+        .unwrap_or(Path::new("-"));
+    write(&output_path(first, output, "")?, &format.encode(&binary))?;
+    Ok(())
+}
+
+//where to write something made from the file at path: wherever it was asked to go, stdout if the file came from stdin,
+//or next to the file with its extension changed (which it has to have if it's just being taken off)
+fn output_path(path: &Path, output: Option<&Path>, extension: &str) -> Result<PathBuf> {
+    match output {
+        Some(output) => Ok(output.to_owned()),
+        None if is_stdio(path) => Ok(PathBuf::from("-")),
+        None if extension.is_empty() && path.extension().is_none() => anyhow::bail!(
+            "{} has no extension to take off, so give somewhere else to write to with --output",
+            path.display()
+        ),
+        None => Ok(path.with_extension(extension)),
+    }
+}
+
+//executes a binary file in any of the output formats, returning how the run went
+//the format is worked out from what's in the file unless given
+//trace is called with a record of every instruction as it's executed
+pub fn execute_file(
+    path: impl AsRef<Path>,
+    format: Option<OutputFormat>,
+    max_cycles: usize,
+    trace: impl FnMut(TraceEntry),
+) -> Result<Run> {
+    let program = read_binary(path.as_ref(), format)?;
+    Ok(Machine::new(&program)?
+        .max_cycles(max_cycles)
+        .run_traced(trace))
+}
+
+//reads a cpu state saved as JSON, or TOML if the file ends in .toml, and carries on executing from it
+#[cfg(feature = "serde")]
+pub fn execute_state_file(
+    path: impl AsRef<Path>,
+    max_cycles: usize,
+    trace: impl FnMut(TraceEntry),
+) -> Result<Run> {
+    let path = path.as_ref();
+    let format = if path.extension().is_some_and(|ext| ext == "toml") {
+        StateFormat::Toml
+    } else {
+        StateFormat::Json
+    };
+    let cpu = format.decode(&read_text(path)?)?;
+    Ok(Machine::from_cpu(cpu)
+        .max_cycles(max_cycles)
+        .run_traced(trace))
+}
+
+//reads a text file, assembles it, and then runs it, returning how the run went
+pub fn run_file(
+    path: impl AsRef<Path>,
+    max_cycles: usize,
+    trace: impl FnMut(TraceEntry),
+    options: &ParseOptions,
+) -> Result<Run> {
+    let path = path.as_ref();
+    let file = read_text(path)?;
 
-    let instructions = parser::parse_file(&file)?;
+    let program = parse_path(&file, path, options)?;
 
-    let binary: Result<Vec<u8>, _> = instructions.into_iter().map(|i| i.assemble()).collect();
+    Ok(Machine::new(&program.bytes)?
+        .max_cycles(max_cycles)
+        .run_traced(trace))
+}
 
-    let final_state = execute_program(&binary?)?;
+//reads a binary file in any of the output formats and writes it out as assembly, to stdout unless told otherwise
+pub fn disassemble_file(
+    path: impl AsRef<Path>,
+    format: Option<OutputFormat>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let file = read_binary(path.as_ref(), format)?;
+    if file.len() > 32 {
+        return Err(CPUError::ProgramTooLarge.into());
+    }
+    write(
+        output.unwrap_or_else(|| Path::new("-")),
+        disassemble(&file).as_bytes(),
+    )?;
+    Ok(())
+}
 
-    println!("Final CPU State: \n{}", final_state);
+//loads either a .patp source file or a binary and steps through it interactively on stdin/stdout
+//source files keep their debug info so the debugger can show which line it's on, and break on labels
+//format is only used for binaries, and is worked out from what's in the file unless given
+pub fn debug_file(
+    path: impl AsRef<Path>,
+    format: Option<OutputFormat>,
+    options: &ParseOptions,
+) -> Result<()> {
+    let path = path.as_ref();
+    //stdin is where the commands come from, so the program can't
+    if is_stdio(path) {
+        anyhow::bail!("The debugger reads commands from stdin, so the program has to be in a file");
+    }
 
+    let (binary, debug_info) = if path.extension().is_some_and(|ext| ext == "patp") {
+        let file = fs::read_to_string(path)?;
+        let program = parse_path(&file, path, options)?;
+        (program.bytes.clone(), program.debug_info().clone())
+    } else {
+        (read_binary(path, format)?, DebugInfo::default())
+    };
+
+    let mut debugger = Debugger::new(&binary, debug_info)?;
+    debugger.run(io::stdin().lock(), io::stdout().lock())?;
     Ok(())
 }
 
+//a path of - means stdin or stdout, so programs can be piped in and out
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents)?;
+        Ok(contents)
+    } else {
+        fs::read(path)
+    }
+}
+
+//reads a program from a binary file in the given format, or whatever format it looks like it's in
+fn read_binary(path: &Path, format: Option<OutputFormat>) -> Result<Vec<u8>> {
+    let contents = read(path)?;
+    Ok(match format {
+        Some(format) => format.decode(&contents)?,
+        None => read_program(&contents)?,
+    })
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    if is_stdio(path) {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if is_stdio(path) {
+        io::stdout().write_all(contents)
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+//reads a config file of extra names for mnemonics into the options, rendering any errors in it ready to print
+pub fn load_aliases(options: &mut ParseOptions, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let config = fs::read_to_string(path)?;
+    options
+        .add_aliases(&config, path)
+        .map_err(|errors| anyhow::anyhow!(render_errors(&errors, &config, path)))
+}
+
+//parses a file that was read in, rendering any errors against its source so they're ready to print
+fn parse_path(file: &str, path: &Path, options: &ParseOptions) -> Result<Program> {
+    let program = assembler(path, options)
+        .assemble(file)
+        .map_err(diagnostics_error)?;
+    print_warnings(&program.diagnostics);
+    Ok(program)
+}
+
+//an assembler for the file at path, set up with the options from the command line
+fn assembler(path: &Path, options: &ParseOptions) -> Assembler {
+    //source from stdin isn't from any file, so includes are relative to the current directory
+    let path = if is_stdio(path) { Path::new("") } else { path };
+    Assembler::new().options(options.clone()).path(path)
+}
+
+//all the errors from assembling something, rendered against the source and ready to print
+fn diagnostics_error(errors: Vec<Diagnostic>) -> anyhow::Error {
+    let rendered: Vec<String> = errors.into_iter().map(|e| e.rendered).collect();
+    anyhow::anyhow!(parser::join_errors(&rendered))
+}
+
+//warnings don't stop anything, so they're printed to stderr straight away
+fn print_warnings(warnings: &[Diagnostic]) {
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+}
+
 //takes a list of instructions and assembles them, returning a vec of bytes
-pub fn assemble_instructions(instructions: &[Instruction]) -> Result<Vec<u8>, cpu::CPUError> {
+pub fn assemble_instructions(instructions: &[Instruction]) -> Result<Vec<u8>, CPUError> {
     instructions
         .iter()
         .map(|i| (*i).assemble())
@@ -61,14 +313,79 @@ pub fn assemble_instructions(instructions: &[Instruction]) -> Result<Vec<u8>, cp
 
 //takes a program as a list of bytes and executes it, returning the final CPU state
 pub fn execute_program(program: &[u8]) -> Result<Cpu> {
-    let mut state = Cpu::new().load(program)?;
-    loop {
-        let instruction = state.fetch();
-        let new_state = state.execute(instruction);
-        match new_state {
-            Ok(new_state) => state = new_state,
-            Err(cpu::CPUError::Stop(end_state)) => return Ok(end_state),
-            _ => unreachable!(),
+    Ok(execute_program_with_limit(program, DEFAULT_MAX_CYCLES)?)
+}
+
+//executes a program for at most max_cycles, returning the final CPU state
+//programs that loop forever are caught as soon as they get back into a state they've been in before
+pub fn execute_program_with_limit(program: &[u8], max_cycles: usize) -> Result<Cpu, CPUError> {
+    Machine::new(program)?
+        .max_cycles(max_cycles)
+        .run()
+        .into_result()
+}
+
+//same as execute_program_with_limit, but calls trace with a record of every instruction executed
+pub fn execute_program_traced(
+    program: &[u8],
+    max_cycles: usize,
+    trace: impl FnMut(TraceEntry),
+) -> Result<Cpu, CPUError> {
+    Machine::new(program)?
+        .max_cycles(max_cycles)
+        .run_traced(trace)
+        .into_result()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assemble(program: &str) -> Vec<u8> {
+        assemble_instructions(&parse_file(program).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_halts() {
+        let program = assemble("CLEAR\nADD 3\nloop: DEC\nBNZ loop\nSTOP");
+        let state = execute_program_with_limit(&program, 100).unwrap();
+        assert_eq!(state.pc(), 5);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        //counts all the way round 256 values before stopping, so needs a few hundred cycles
+        let program = assemble("loop: INC\nBNZ loop\nSTOP");
+        assert!(matches!(
+            execute_program_with_limit(&program, 100),
+            Err(CPUError::StepLimit(100, _))
+        ));
+        assert!(execute_program_with_limit(&program, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_infinite_loops() {
+        //jump to itself
+        let program = assemble("CLEAR\nself: JUMP self");
+        match execute_program_with_limit(&program, 1000) {
+            Err(CPUError::InfiniteLoop(1, state)) => assert_eq!(state.pc(), 1),
+            other => panic!("expected an infinite loop, got {:?}", other),
         }
+
+        //a longer loop that changes the register as it goes
+        //memory at 20 is only written on the first time round, so the state first repeats at the JUMP
+        let program = assemble("CLEAR\nINC\nloop: ADD 1\nDEC\nSTORE 20\nJUMP loop");
+        assert!(matches!(
+            execute_program_with_limit(&program, 1000),
+            Err(CPUError::InfiniteLoop(5, _))
+        ));
+
+        //the register keeps changing, so this isn't caught until it wraps around
+        //the Z flag starts clear with the register at 0, which never happens again, so the loop starts at the JUMP
+        let program = assemble("loop: INC\nJUMP loop");
+        assert!(matches!(
+            execute_program_with_limit(&program, 10_000),
+            Err(CPUError::InfiniteLoop(1, _))
+        ));
     }
 }
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use crate::parser::DebugInfo;
+
+//produces an assembler listing: every line of source next to the address and byte it assembled to,
+//followed by the symbol table
+pub fn listing(source: &str, binary: &[u8], debug_info: &DebugInfo) -> String {
+    //the source map goes address -> line, we want line -> addresses (data lines can take up several)
+    let mut addresses: HashMap<usize, Vec<u8>> = HashMap::new();
+    for (address, line) in &debug_info.source_map {
+        addresses.entry(line.number).or_default().push(*address);
+    }
+
+    let mut listing = String::from("Addr  Hex  Binary     Line  Source\n");
+
+    for (line_no, text) in source.lines().enumerate() {
+        let line_no = line_no + 1;
+        let text = text.trim_end();
+        let bytes: Vec<(u8, u8)> = addresses
+            .get(&line_no)
+            .into_iter()
+            .flatten()
+            .filter_map(|address| Some((*address, *binary.get(*address as usize)?)))
+            .collect();
+
+        if bytes.is_empty() {
+            listing.push_str(format!("{:<24}{:>4}  {}", "", line_no, text).trim_end());
+            listing.push('\n');
+        }
+
+        //the source only goes next to the first byte of the line
+        for (i, (address, byte)) in bytes.into_iter().enumerate() {
+            let line = format!(
+                "{:>4}  {:02x}   {:03b} {:05b}",
+                address,
+                byte,
+                byte >> 5,
+                byte & 0b0001_1111
+            );
+            if i == 0 {
+                listing.push_str(format!("{}  {:>4}  {}", line, line_no, text).trim_end());
+            } else {
+                listing.push_str(&line);
+            }
+            listing.push('\n');
+        }
+    }
+
+    //sorted by address, so they read in the same order as the program
+    let mut symbols: Vec<(&String, &usize)> = debug_info.symbols.iter().collect();
+    symbols.sort_by_key(|(name, address)| (**address, *name));
+
+    listing.push_str("\nSymbols:\n");
+    if symbols.is_empty() {
+        listing.push_str("  (none)\n");
+    }
+    let width = symbols
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, address) in symbols {
+        listing.push_str(&format!(
+            "  {:<width$}  {:>2}\n",
+            name,
+            address,
+            width = width
+        ));
+    }
+    listing
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assemble_instructions, parse_file_with_source};
+
+    #[test]
+    fn test_listing() {
+        let source = "; count down\nstart: ADD 3  \n\nloop: DEC ; round we go\n  BNZ loop\nSTOP\nx: DB 7, 255";
+        let (instructions, debug_info) = parse_file_with_source(source).unwrap();
+        let binary = assemble_instructions(&instructions).unwrap();
+
+        assert_eq!(
+            listing(source, &binary, &debug_info),
+            "Addr  Hex  Binary     Line  Source
+                           1  ; count down
+   0  43   010 00011     2  start: ADD 3
+                           3
+   1  60   011 00000     4  loop: DEC ; round we go
+   2  a1   101 00001     5    BNZ loop
+   3  01   000 00001     6  STOP
+   4  07   000 00111     7  x: DB 7, 255
+   5  ff   111 11111
+
+Symbols:
+  start   0
+  loop    1
+  x       4
+"
+        );
+    }
+}
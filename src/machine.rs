@@ -0,0 +1,448 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::cpu::{CPUError, Cpu};
+use crate::instruction::Instruction;
+use crate::trace::TraceEntry;
+
+//the number of cycles a program gets to halt in, unless told otherwise
+pub const DEFAULT_MAX_CYCLES: usize = 1_000_000;
+
+//runs a program, pausing wherever it's been told to and reporting why it stopped rather than printing anything
+//the whole machine state is tiny, so it's checked for repeats as it goes to catch programs that loop forever
+#[derive(Debug, Clone)]
+pub struct Machine {
+    cpu: Cpu,
+    cycles: usize,
+    halted: bool,
+    max_cycles: usize,
+    breakpoints: BTreeSet<u8>,
+    watchpoints: Vec<Watchpoint>,
+    //brent's cycle detection: the tortoise teleports to the hare every power of two steps
+    //so we only ever keep one old state around, along with the one we started from to find where the loop starts
+    start: Cpu,
+    tortoise: Cpu,
+    power: usize,
+    length: usize,
+}
+
+//a memory address to keep an eye on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Watchpoint {
+    //triggers on any STORE to the address, even if it writes the same value
+    Store(u8),
+    //triggers whenever the value at the address changes
+    Change(u8),
+}
+
+//why a run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RunOutcome {
+    Halted,
+    //ran for as many cycles as it was allowed without halting
+    StepLimit,
+    //got back into a state it's been in before, so it'll loop forever from the given address
+    LoopDetected(u8),
+    //hit a breakpoint at the given address, before executing it
+    Breakpoint(u8),
+    //a watchpoint triggered, with the old and new values at the address
+    Watchpoint {
+        watchpoint: Watchpoint,
+        old: u8,
+        new: u8,
+    },
+}
+
+//everything about where a machine is up to, so it can be put back there
+//breakpoints and watchpoints aren't part of it, so they stay as they are when it's restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    cpu: Cpu,
+    cycles: usize,
+    halted: bool,
+    start: Cpu,
+    tortoise: Cpu,
+    power: usize,
+    length: usize,
+}
+
+//the result of a run: why it stopped, how many cycles the machine has executed in total, and the state it stopped in
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Run {
+    pub outcome: RunOutcome,
+    pub cycles: usize,
+    pub cpu: Cpu,
+}
+
+impl Machine {
+    //load a program into a fresh cpu
+    pub fn new(program: &[u8]) -> Result<Machine, CPUError> {
+        Ok(Machine::from_cpu(Cpu::new().load(program)?))
+    }
+
+    //start from any state, rather than a freshly loaded program
+    pub fn from_cpu(cpu: Cpu) -> Machine {
+        Machine {
+            cpu: cpu.clone(),
+            cycles: 0,
+            halted: false,
+            max_cycles: DEFAULT_MAX_CYCLES,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            start: cpu.clone(),
+            tortoise: cpu,
+            power: 1,
+            length: 0,
+        }
+    }
+
+    //the most cycles each run gets before giving up
+    pub fn max_cycles(mut self, max_cycles: usize) -> Machine {
+        self.max_cycles = max_cycles;
+        self
+    }
+
+    pub fn add_breakpoint(&mut self, address: u8) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    //removes any breakpoints or watchpoints on an address, returning whether there were any
+    pub fn delete(&mut self, address: u8) -> bool {
+        let watchpoints = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.address() != address);
+        self.breakpoints.remove(&address) || watchpoints != self.watchpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u8> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            cycles: self.cycles,
+            halted: self.halted,
+            start: self.start.clone(),
+            tortoise: self.tortoise.clone(),
+            power: self.power,
+            length: self.length,
+        }
+    }
+
+    //rewind (or fast forward) to a snapshot, which can be from any machine
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let snapshot = snapshot.clone();
+        self.cpu = snapshot.cpu;
+        self.cycles = snapshot.cycles;
+        self.halted = snapshot.halted;
+        self.start = snapshot.start;
+        self.tortoise = snapshot.tortoise;
+        self.power = snapshot.power;
+        self.length = snapshot.length;
+    }
+
+    //executes a single instruction, returning it along with the address it was fetched from
+    //returns None if the program has already halted
+    pub fn step(&mut self) -> Option<(u8, Instruction)> {
+        self.advance(&mut |_| ())
+            .map(|(pc, instruction, _)| (pc, instruction))
+    }
+
+    //steps once, returning an outcome if execution should stop there
+    //returns None if the program has already halted, or if there's no reason to stop
+    pub fn step_checked(&mut self) -> Option<RunOutcome> {
+        self.advance(&mut |_| ())
+            .and_then(|(_, _, outcome)| outcome)
+    }
+
+    //keep stepping until the program halts, hits a breakpoint or watchpoint, loops forever, or runs out of cycles
+    //running again carries on from wherever it stopped, with another max_cycles to go
+    pub fn run(&mut self) -> Run {
+        self.run_traced(|_| ())
+    }
+
+    //same as run, but calls trace with a record of every instruction executed
+    pub fn run_traced(&mut self, mut trace: impl FnMut(TraceEntry)) -> Run {
+        if self.halted {
+            return self.report(RunOutcome::Halted);
+        }
+        for _ in 0..self.max_cycles {
+            if let Some((_, _, Some(outcome))) = self.advance(&mut trace) {
+                return self.report(outcome);
+            }
+        }
+        self.report(RunOutcome::StepLimit)
+    }
+
+    fn report(&self, outcome: RunOutcome) -> Run {
+        Run {
+            outcome,
+            cycles: self.cycles,
+            cpu: self.cpu.clone(),
+        }
+    }
+
+    //executes an instruction, working out whether that's a reason to stop
+    fn advance(
+        &mut self,
+        trace: &mut dyn FnMut(TraceEntry),
+    ) -> Option<(u8, Instruction, Option<RunOutcome>)> {
+        if self.halted {
+            return None;
+        }
+        let before = self.cpu.clone();
+        let pc = self.cpu.pc();
+        let instruction = self.cpu.fetch();
+        self.cycles += 1;
+        self.halted = !self.cpu.apply(instruction);
+        trace(TraceEntry::new(self.cycles, &before, &self.cpu));
+
+        if self.halted {
+            return Some((pc, instruction, Some(RunOutcome::Halted)));
+        }
+        let looped = self.check_loop();
+
+        let (before, after) = (before.memory(), self.cpu.memory());
+        let triggered = self.watchpoints.iter().find(|w| match **w {
+            Watchpoint::Store(address) => instruction == Instruction::Store(address),
+            Watchpoint::Change(address) => before[address as usize] != after[address as usize],
+        });
+        let outcome = if let Some(&watchpoint) = triggered {
+            let address = watchpoint.address() as usize;
+            Some(RunOutcome::Watchpoint {
+                watchpoint,
+                old: before[address],
+                new: after[address],
+            })
+        } else if self.breakpoints.contains(&self.cpu.pc()) {
+            Some(RunOutcome::Breakpoint(self.cpu.pc()))
+        } else {
+            looped.map(RunOutcome::LoopDetected)
+        };
+        Some((pc, instruction, outcome))
+    }
+
+    //moves the cycle detection along a step, returning where the loop starts if the state has repeated
+    fn check_loop(&mut self) -> Option<u8> {
+        self.length += 1;
+        let looped =
+            (self.cpu == self.tortoise).then(|| find_loop_start(&self.start, self.length).pc());
+        if self.length == self.power {
+            self.tortoise = self.cpu.clone();
+            self.power *= 2;
+            self.length = 0;
+        }
+        looped
+    }
+}
+
+//we know the program loops with the given length, so run two copies that far apart from the start
+//the first state they agree on is where the loop begins
+fn find_loop_start(start: &Cpu, length: usize) -> Cpu {
+    let step = |mut cpu: Cpu| {
+        let instruction = cpu.fetch();
+        cpu.apply(instruction);
+        cpu
+    };
+
+    let mut tortoise = start.clone();
+    let mut hare = (0..length).fold(start.clone(), |cpu, _| step(cpu));
+    while tortoise != hare {
+        tortoise = step(tortoise);
+        hare = step(hare);
+    }
+    tortoise
+}
+
+impl Snapshot {
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+}
+
+impl Run {
+    //only a halted program counts as having finished, anything else is an error carrying the state it stopped in
+    pub fn into_result(self) -> Result<Cpu, CPUError> {
+        match self.outcome {
+            RunOutcome::Halted => Ok(self.cpu),
+            RunOutcome::StepLimit => Err(CPUError::StepLimit(self.cycles, self.cpu)),
+            RunOutcome::LoopDetected(address) => Err(CPUError::InfiniteLoop(address, self.cpu)),
+            outcome => Err(CPUError::Paused(outcome, self.cpu)),
+        }
+    }
+}
+
+impl Watchpoint {
+    pub fn address(&self) -> u8 {
+        match *self {
+            Watchpoint::Store(address) | Watchpoint::Change(address) => address,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watchpoint::Store(address) => write!(f, "store to {}", address),
+            Watchpoint::Change(address) => write!(f, "change at {}", address),
+        }
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RunOutcome::Halted => write!(f, "Program halted"),
+            RunOutcome::StepLimit => write!(f, "Program ran out of cycles"),
+            RunOutcome::LoopDetected(address) => {
+                write!(f, "Program loops forever from address {}", address)
+            }
+            RunOutcome::Breakpoint(address) => write!(f, "Breakpoint at {}", address),
+            RunOutcome::Watchpoint {
+                watchpoint,
+                old,
+                new,
+            } => write!(f, "Watchpoint ({}): {} -> {}", watchpoint, old, new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_file;
+
+    fn load(program: &str) -> Machine {
+        let binary = crate::assemble_instructions(&parse_file(program).unwrap()).unwrap();
+        Machine::new(&binary).unwrap()
+    }
+
+    #[test]
+    fn outcomes() {
+        let run = load("CLEAR\nADD 3\nloop: DEC\nBNZ loop\nSTOP").run();
+        assert_eq!(run.outcome, RunOutcome::Halted);
+        assert_eq!(run.cycles, 9);
+        assert_eq!(run.cpu.pc(), 5);
+
+        //counts all the way round 256 values before stopping, so needs a few hundred cycles
+        let mut machine = load("loop: INC\nBNZ loop\nSTOP").max_cycles(100);
+        let run = machine.run();
+        assert_eq!(run.outcome, RunOutcome::StepLimit);
+        assert_eq!(run.cycles, 100);
+        //running again gets another 100 cycles
+        assert_eq!(machine.run().cycles, 200);
+        assert_eq!(machine.max_cycles(1000).run().outcome, RunOutcome::Halted);
+
+        let run = load("CLEAR\nself: JUMP self").run();
+        assert_eq!(run.outcome, RunOutcome::LoopDetected(1));
+        assert_eq!(run.cpu.pc(), 1);
+
+        //halted programs stay halted
+        let mut machine = load("STOP");
+        assert_eq!(machine.run().outcome, RunOutcome::Halted);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.run().cycles, 1);
+    }
+
+    #[test]
+    fn pausing() {
+        let mut machine = load("CLEAR\nADD 2\nloop: DEC\nBNZ loop\nSTORE 31\nSTOP");
+        machine.add_breakpoint(2);
+        machine.add_watchpoint(Watchpoint::Store(31));
+        assert_eq!(machine.run().outcome, RunOutcome::Breakpoint(2));
+        assert_eq!(machine.run().outcome, RunOutcome::Breakpoint(2));
+        let run = machine.run();
+        assert_eq!(
+            run.outcome,
+            RunOutcome::Watchpoint {
+                watchpoint: Watchpoint::Store(31),
+                old: 0,
+                new: 0
+            }
+        );
+        assert_eq!(run.cycles, 7);
+        assert_eq!(
+            run.into_result(),
+            Err(CPUError::Paused(
+                RunOutcome::Watchpoint {
+                    watchpoint: Watchpoint::Store(31),
+                    old: 0,
+                    new: 0
+                },
+                machine.cpu().clone()
+            ))
+        );
+        assert_eq!(machine.run().outcome, RunOutcome::Halted);
+    }
+
+    //set up a state by hand, run from it, then go back and run it again differently
+    #[test]
+    fn snapshots() {
+        let mut memory = [0; 32];
+        memory[10..13].copy_from_slice(&[0b0110_0000, 0b1010_1010, 1]);
+        let cpu = Cpu::new().with_memory(memory).with_register(3).with_pc(10);
+        assert_eq!((cpu.pc(), cpu.register(), cpu.z()), (10, 3, false));
+        assert_eq!(cpu.clone().with_pc(40).pc(), 8);
+
+        let mut machine = Machine::from_cpu(cpu);
+        let start = machine.snapshot();
+        let run = machine.run();
+        assert_eq!(run.outcome, RunOutcome::Halted);
+        assert_eq!((run.cycles, run.cpu.register(), run.cpu.z()), (7, 0, true));
+
+        machine.restore(&start);
+        assert_eq!(machine.cycles(), 0);
+        assert!(!machine.is_halted());
+        assert_eq!(machine.cpu(), start.cpu());
+        assert_eq!(machine.run(), run);
+
+        //changing the state part way through
+        machine.restore(&start);
+        machine.step();
+        let mut cpu = machine.cpu().clone();
+        cpu.set_register(1);
+        cpu.set_z(false);
+        cpu.set_pc(10);
+        cpu.memory_mut()[12] = 0b0000_0010;
+        let run = Machine::from_cpu(cpu).run();
+        assert_eq!(run.outcome, RunOutcome::Halted);
+        assert_eq!((run.cycles, run.cpu.register()), (3, 0));
+    }
+
+    #[test]
+    fn traced() {
+        let mut pcs = Vec::new();
+        let run = load("INC\nDEC\nSTOP").run_traced(|entry| pcs.push(entry.pc));
+        assert_eq!(run.outcome, RunOutcome::Halted);
+        assert_eq!(pcs, [0, 1, 2]);
+    }
+}
@@ -1,39 +1,287 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
 use anyhow::Result;
-use clap::{ArgEnum, Parser};
+use clap::{Args, Parser, Subcommand};
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+fn main() {
+    //errors are printed here rather than returned, so assembler diagnostics come out as they're formatted
+    if let Err(error) = run(Cli::parse()) {
+        eprintln!("error: {:#}", error);
+        std::process::exit(1);
+    }
+}
 
+fn run(args: Cli) -> Result<()> {
     match args.command {
-        Command::Assemble => patp::execute_file(args.file)?,
-        Command::Emulate => patp::assemble_file(args.file)?,
-        Command::Run => patp::run_file(args.file)?,
+        Command::Assemble {
+            file,
+            output,
+            object: true,
+            source,
+            ..
+        } => patp::assemble_object_file(file, output.as_deref(), &source.options()?)?,
+        Command::Assemble {
+            file,
+            output,
+            format,
+            listing,
+            object: false,
+            source,
+        } => patp::assemble_file(file, output.as_deref(), format, listing, &source.options()?)?,
+        Command::Link {
+            files,
+            output,
+            format,
+        } => patp::link_files(&files, output.as_deref(), format)?,
+        #[cfg(feature = "serde")]
+        Command::Emulate {
+            file,
+            execution,
+            from_state: true,
+            ..
+        } => {
+            let run = patp::execute_state_file(file, execution.max_cycles, execution.tracer())?;
+            execution.finish(run)?;
+        }
+        Command::Emulate {
+            file,
+            input,
+            execution,
+            ..
+        } => {
+            let run = patp::execute_file(
+                file,
+                input.format(),
+                execution.max_cycles,
+                execution.tracer(),
+            )?;
+            execution.finish(run)?;
+        }
+        Command::Run {
+            file,
+            execution,
+            source,
+        } => {
+            let run = patp::run_file(
+                file,
+                execution.max_cycles,
+                execution.tracer(),
+                &source.options()?,
+            )?;
+            execution.finish(run)?;
+        }
+        Command::Debug {
+            file,
+            input,
+            source,
+        } => patp::debug_file(file, input.format(), &source.options()?)?,
+        Command::Disassemble {
+            file,
+            input,
+            output,
+        } => patp::disassemble_file(file, input.format(), output.as_deref())?,
     }
     Ok(())
 }
 
 //cli/clap stuff
+//anywhere a file is read or written, - means stdin or stdout instead
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 struct Cli {
-    #[clap(arg_enum)]
+    #[clap(subcommand)]
     command: Command,
-    #[clap(validator = file_exists)]
-    file: String,
 }
 
-#[derive(Copy, Clone, Debug, ArgEnum)]
+#[derive(Subcommand)]
 enum Command {
-    Assemble,
-    Emulate,
-    Run,
+    /// Assemble a .patp file into a binary
+    Assemble {
+        #[clap(validator = file_exists)]
+        file: PathBuf,
+        /// Where to write the binary, which by default goes next to the source with the extension taken off
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// What to write the binary as: raw bytes, hex or bin text with a byte per line, ihex (Intel HEX),
+        /// readmemh or readmemb for Verilog, or logisim for a Logisim-evolution ROM image
+        #[clap(long, default_value = "raw")]
+        format: patp::OutputFormat,
+        /// Also write a .lst listing file next to the binary, showing the address and encoding of each line
+        #[clap(long)]
+        listing: bool,
+        /// Write a relocatable object to be linked with others, which by default goes next to the source as a .o file.
+        /// It can use symbols declared with EXTERN from other objects, and shares any declared with GLOBAL
+        #[clap(short = 'c', long, conflicts_with_all = &["format", "listing"])]
+        object: bool,
+        #[clap(flatten)]
+        source: SourceArgs,
+    },
+    /// Execute a binary file, in any of the formats assemble can write, and print the final CPU state
+    Emulate {
+        #[clap(validator = file_exists)]
+        file: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        execution: ExecutionArgs,
+        /// The file is a CPU state saved with --state, as JSON (or TOML if it ends in .toml), to carry on executing from
+        #[cfg(feature = "serde")]
+        #[clap(long, conflicts_with = "input-format")]
+        from_state: bool,
+    },
+    /// Assemble a .patp file and execute it, printing the final CPU state
+    Run {
+        #[clap(validator = file_exists)]
+        file: PathBuf,
+        #[clap(flatten)]
+        execution: ExecutionArgs,
+        #[clap(flatten)]
+        source: SourceArgs,
+    },
+    /// Step through a .patp file or a binary interactively
+    Debug {
+        #[clap(validator = file_exists)]
+        file: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        #[clap(flatten)]
+        source: SourceArgs,
+    },
+    /// Link objects made by assemble --object into a binary, placing them in memory in the order given
+    Link {
+        #[clap(required = true, validator = file_exists)]
+        files: Vec<PathBuf>,
+        /// Where to write the binary, which by default goes next to the first object with the extension taken off
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// What to write the binary as, the same as for assemble
+        #[clap(long, default_value = "raw")]
+        format: patp::OutputFormat,
+    },
+    /// Print a binary file as assembly
+    Disassemble {
+        #[clap(validator = file_exists)]
+        file: PathBuf,
+        #[clap(flatten)]
+        input: InputArgs,
+        /// Where to write the assembly, which by default is printed
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+//options for anything that reads a binary
+#[derive(Args)]
+struct InputArgs {
+    /// What the binary is written as, the same as for assemble, or auto to work it out from what's in the file
+    #[clap(long = "input-format", default_value = "auto")]
+    input_format: InputFormat,
+}
+
+//which format to read a binary as, where auto (None) means it's detected
+struct InputFormat(Option<patp::OutputFormat>);
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputFormat(None)),
+            _ => s.parse().map(|format| InputFormat(Some(format))),
+        }
+    }
+}
+
+impl InputArgs {
+    fn format(&self) -> Option<patp::OutputFormat> {
+        self.input_format.0
+    }
+}
+
+//options for anything that assembles source
+#[derive(Args)]
+struct SourceArgs {
+    /// Treat labels and other symbols the same whatever case they're written in, so Loop, loop and LOOP are all the same label
+    #[clap(long)]
+    ignore_case: bool,
+    /// A file of extra names for instructions, one per line like HALT = STOP
+    #[clap(long, validator = file_exists)]
+    aliases: Option<PathBuf>,
+}
+
+impl SourceArgs {
+    fn options(&self) -> Result<patp::ParseOptions> {
+        let mut options = patp::ParseOptions::default();
+        options.case_insensitive = self.ignore_case;
+        if let Some(aliases) = &self.aliases {
+            patp::load_aliases(&mut options, aliases)?;
+        }
+        Ok(options)
+    }
+}
+
+//options for anything that executes a program
+#[derive(Args)]
+struct ExecutionArgs {
+    /// Give up on programs that haven't halted after this many cycles
+    #[clap(long, default_value_t = patp::DEFAULT_MAX_CYCLES)]
+    max_cycles: usize,
+    /// Print every instruction as it's executed, as text, csv or json (one object per line)
+    #[clap(long)]
+    trace: Option<patp::TraceFormat>,
+    /// Don't print the final CPU state, only whether the program halted (as the exit code) and any trace asked for
+    #[clap(short, long)]
+    quiet: bool,
+    /// Print the final CPU state as json or toml instead, along with why the program stopped and how many cycles it ran for,
+    /// to be read by other programs or carried on from with emulate --from-state. It's printed even if the program doesn't halt
+    #[cfg(feature = "serde")]
+    #[clap(long, conflicts_with = "quiet")]
+    state: Option<patp::StateFormat>,
+}
+
+impl ExecutionArgs {
+    //prints each instruction as it's executed, in whatever format was asked for, with any header first
+    fn tracer(&self) -> impl FnMut(patp::TraceEntry) {
+        let format = self.trace;
+        let mut started = false;
+        move |entry| {
+            if let Some(format) = format {
+                if !started {
+                    if let Some(header) = format.header() {
+                        println!("{}", header);
+                    }
+                    started = true;
+                }
+                println!("{}", entry.format(format));
+            }
+        }
+    }
+
+    //prints the final state, failing if the program didn't halt
+    //a saved state is printed however the run ended, with why it stopped, so it can always be read
+    fn finish(&self, run: patp::Run) -> Result<()> {
+        #[cfg(feature = "serde")]
+        if let Some(format) = self.state {
+            println!("{}", format.encode(&run));
+            if run.outcome != patp::RunOutcome::Halted {
+                anyhow::bail!("{}", run.outcome);
+            }
+            return Ok(());
+        }
+        let state = run.into_result()?;
+        if !self.quiet {
+            println!("Final CPU State: \n{}", state);
+        }
+        Ok(())
+    }
 }
 
 fn file_exists(f: &str) -> Result<(), &'static str> {
     let p = std::path::Path::new(f);
-    if !p.is_file() {
+    if f != "-" && !p.is_file() {
         Err("File does not exist.")
     } else {
         Ok(())
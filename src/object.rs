@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+//a relocatable object: one module of a program assembled on its own, so that it can be linked with others
+//addresses in an object count from the start of the module, and are moved to wherever it ends up when it's linked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+    pub code: Vec<u8>,
+    pub exports: Vec<Export>,
+    pub references: Vec<Reference>,
+    pub relocations: Vec<Relocation>,
+}
+
+//which part of a byte holds an address or symbol: the operand of an instruction, or the whole byte for data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Operand,
+    Byte,
+}
+
+//a symbol the module shares with others, which is either an address in the module or just a number
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub value: u8,
+    pub relocatable: bool,
+}
+
+//a use of a symbol from another module, whose value is added to whatever's already in the field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    pub offset: u8,
+    pub field: Field,
+    pub symbol: String,
+}
+
+//an address in the module, which has the address the module is linked at added to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: u8,
+    pub field: Field,
+}
+
+//errors reading an object file back in
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectError {
+    #[error("Not an object file: the first line should be \"{}\"", HEADER)]
+    MissingHeader,
+
+    #[error("Line {0}: unrecognised input")]
+    BadInput(usize),
+
+    #[error("Line {0}: offset {1} is past the end of the code")]
+    OffsetOutOfRange(usize, u8),
+}
+
+//errors linking objects together, which say which module they're in by the name it was given
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum LinkError {
+    #[error("Program is too long: the modules take up {0} bytes (max 32)\n{1}")]
+    ProgramTooLong(usize, MemoryMap),
+
+    #[error("Symbol {0} is exported by both {2} and {1}")]
+    SymbolRedefined(String, String, String),
+
+    #[error("Unknown symbol {0}, used by {1}")]
+    InvalidSymbol(String, String),
+
+    #[error("The operand at offset {1} of {0} is out of range once linked")]
+    OperandOverflow(String, u8),
+}
+
+//where each module goes in memory, in the order they're linked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryMap(pub Vec<(String, usize, usize)>);
+
+//a line per module, with its start and end address, marking anything that doesn't fit
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, start, end) in &self.0 {
+            write!(f, "{:>3}..{:<3} {}", start, end, name)?;
+            if *end > 32 {
+                write!(f, " (past the end of memory)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+//the first line of every object file
+const HEADER: &str = "PATP object";
+
+//objects are written as text, a line for each part:
+//code, then a line each for every export, reference and relocation
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        write!(f, "code")?;
+        for byte in &self.code {
+            write!(f, " {:02x}", byte)?;
+        }
+        writeln!(f)?;
+        for export in &self.exports {
+            let kind = if export.relocatable {
+                "address"
+            } else {
+                "number"
+            };
+            writeln!(f, "export {} {} {}", export.name, export.value, kind)?;
+        }
+        for reference in &self.references {
+            writeln!(
+                f,
+                "ref {} {} {}",
+                reference.offset, reference.field, reference.symbol
+            )?;
+        }
+        for relocation in &self.relocations {
+            writeln!(f, "reloc {} {}", relocation.offset, relocation.field)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Operand => write!(f, "operand"),
+            Field::Byte => write!(f, "byte"),
+        }
+    }
+}
+
+impl FromStr for Object {
+    type Err = ObjectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => (),
+            _ => return Err(ObjectError::MissingHeader),
+        }
+
+        let mut object = Object::default();
+        let mut offsets = Vec::new();
+        for (number, line) in lines {
+            let number = number + 1;
+            let bad = || ObjectError::BadInput(number);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let field = |word: &str| match word {
+                "operand" => Ok(Field::Operand),
+                "byte" => Ok(Field::Byte),
+                _ => Err(bad()),
+            };
+            match words.as_slice() {
+                [] => (),
+                ["code", bytes @ ..] => {
+                    object.code = bytes
+                        .iter()
+                        .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| bad()))
+                        .collect::<Result<_, _>>()?;
+                }
+                ["export", name, value, kind] => object.exports.push(Export {
+                    name: name.to_string(),
+                    value: value.parse().map_err(|_| bad())?,
+                    relocatable: match *kind {
+                        "address" => true,
+                        "number" => false,
+                        _ => return Err(bad()),
+                    },
+                }),
+                ["ref", offset, kind, symbol] => {
+                    let offset = offset.parse().map_err(|_| bad())?;
+                    offsets.push((number, offset));
+                    object.references.push(Reference {
+                        offset,
+                        field: field(kind)?,
+                        symbol: symbol.to_string(),
+                    });
+                }
+                ["reloc", offset, kind] => {
+                    let offset = offset.parse().map_err(|_| bad())?;
+                    offsets.push((number, offset));
+                    object.relocations.push(Relocation {
+                        offset,
+                        field: field(kind)?,
+                    });
+                }
+                _ => return Err(bad()),
+            }
+        }
+
+        //the code can come after the references to it, so they're only checked once it's all been read
+        if let Some((number, offset)) = offsets
+            .into_iter()
+            .find(|(_, offset)| *offset as usize >= object.code.len())
+        {
+            return Err(ObjectError::OffsetOutOfRange(number, offset));
+        }
+        Ok(object)
+    }
+}
+
+//adds a value to the address held in a field of a byte, if the result still fits
+fn add(byte: u8, field: Field, value: usize) -> Option<u8> {
+    match field {
+        Field::Operand => {
+            let operand = (byte & 0b0001_1111) as usize + value;
+            (operand < 32).then_some((byte & 0b1110_0000) | operand as u8)
+        }
+        Field::Byte => (byte as usize + value).try_into().ok(),
+    }
+}
+
+//links modules into a single program, laying them out one after the other in the order given
+//each module has a name, which is what any errors call it
+//every error that can be found is returned, not just the first
+pub fn link(modules: &[(String, Object)]) -> Result<Vec<u8>, Vec<LinkError>> {
+    let mut errors = Vec::new();
+
+    let mut map = Vec::new();
+    let mut address = 0;
+    for (name, object) in modules {
+        map.push((name.clone(), address, address + object.code.len()));
+        address += object.code.len();
+    }
+    if address > 32 {
+        //nothing else is worth checking if there's nowhere to put it
+        return Err(vec![LinkError::ProgramTooLong(address, MemoryMap(map))]);
+    }
+
+    //every exported symbol, with the value it ends up with and the module that exported it
+    let mut symbols: HashMap<&str, (usize, &str)> = HashMap::new();
+    for ((name, object), (_, start, _)) in modules.iter().zip(&map) {
+        for export in &object.exports {
+            let value = export.value as usize + if export.relocatable { *start } else { 0 };
+            match symbols.get(export.name.as_str()) {
+                Some((_, first)) => errors.push(LinkError::SymbolRedefined(
+                    export.name.clone(),
+                    name.clone(),
+                    first.to_string(),
+                )),
+                None => {
+                    symbols.insert(&export.name, (value, name));
+                }
+            }
+        }
+    }
+
+    let mut program = Vec::new();
+    for ((name, object), (_, start, _)) in modules.iter().zip(&map) {
+        let mut code = object.code.clone();
+        let relocations = object
+            .relocations
+            .iter()
+            .map(|relocation| (relocation.offset, relocation.field, *start));
+        let references = object.references.iter().filter_map(|reference| {
+            match symbols.get(reference.symbol.as_str()) {
+                Some((value, _)) => Some((reference.offset, reference.field, *value)),
+                None => {
+                    errors.push(LinkError::InvalidSymbol(
+                        reference.symbol.clone(),
+                        name.clone(),
+                    ));
+                    None
+                }
+            }
+        });
+        for (offset, field, value) in relocations.chain(references).collect::<Vec<_>>() {
+            match code
+                .get(offset as usize)
+                .and_then(|byte| add(*byte, field, value))
+            {
+                Some(byte) => code[offset as usize] = byte,
+                None => errors.push(LinkError::OperandOverflow(name.clone(), offset)),
+            }
+        }
+        program.extend(code);
+    }
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //a module that jumps to a routine in another, which jumps back to the label it exports
+    fn modules() -> Vec<(String, Object)> {
+        let main = Object {
+            //CLEAR, JUMP double, back: STOP
+            code: vec![0b0000_0000, 0b1000_0000, 0b0000_0001],
+            exports: vec![Export {
+                name: "back".to_owned(),
+                value: 2,
+                relocatable: true,
+            }],
+            references: vec![Reference {
+                offset: 1,
+                field: Field::Operand,
+                symbol: "double".to_owned(),
+            }],
+            relocations: vec![],
+        };
+        let library = Object {
+            //double: ADD 3, JUMP back, DB double+1
+            code: vec![0b0100_0011, 0b1000_0000, 1],
+            exports: vec![
+                Export {
+                    name: "double".to_owned(),
+                    value: 0,
+                    relocatable: true,
+                },
+                Export {
+                    name: "THREE".to_owned(),
+                    value: 3,
+                    relocatable: false,
+                },
+            ],
+            references: vec![Reference {
+                offset: 1,
+                field: Field::Operand,
+                symbol: "back".to_owned(),
+            }],
+            relocations: vec![Relocation {
+                offset: 2,
+                field: Field::Byte,
+            }],
+        };
+        vec![("main".to_owned(), main), ("library".to_owned(), library)]
+    }
+
+    #[test]
+    fn linking() {
+        assert_eq!(
+            link(&modules()),
+            Ok(vec![
+                0b0000_0000,
+                0b1000_0011,
+                0b0000_0001,
+                0b0100_0011,
+                0b1000_0010,
+                4
+            ])
+        );
+
+        let mut modules = modules();
+        modules[0].1.references[0].symbol = "triple".to_owned();
+        modules[1].1.exports[1].name = "double".to_owned();
+        assert_eq!(
+            link(&modules),
+            Err(vec![
+                LinkError::SymbolRedefined(
+                    "double".to_owned(),
+                    "library".to_owned(),
+                    "library".to_owned()
+                ),
+                LinkError::InvalidSymbol("triple".to_owned(), "main".to_owned()),
+            ])
+        );
+
+        //the relocated operand no longer fits once the library is moved up
+        let mut modules = self::modules();
+        modules[1].1.code[1] = 0b1000_0000 | 30;
+        modules[1].1.relocations.push(Relocation {
+            offset: 1,
+            field: Field::Operand,
+        });
+        modules[1].1.references.clear();
+        assert_eq!(
+            link(&modules),
+            Err(vec![LinkError::OperandOverflow("library".to_owned(), 1)])
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut modules = modules();
+        modules[1].1.code.resize(30, 0);
+        let errors = link(&modules).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LinkError::ProgramTooLong(
+                33,
+                MemoryMap(vec![
+                    ("main".to_owned(), 0, 3),
+                    ("library".to_owned(), 3, 33)
+                ])
+            )]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "Program is too long: the modules take up 33 bytes (max 32)\n  0..3   main\n  3..33  library (past the end of memory)\n"
+        );
+    }
+
+    #[test]
+    fn text() {
+        let (_, library) = modules().remove(1);
+        let text = library.to_string();
+        assert_eq!(
+            text,
+            "PATP object\ncode 43 80 01\nexport double 0 address\nexport THREE 3 number\nref 1 operand back\nreloc 2 byte\n"
+        );
+        assert_eq!(text.parse(), Ok(library));
+
+        assert_eq!("code 00".parse::<Object>(), Err(ObjectError::MissingHeader));
+        assert_eq!(
+            "PATP object\ncode 00\nreloc 1 operand".parse::<Object>(),
+            Err(ObjectError::OffsetOutOfRange(3, 1))
+        );
+        assert_eq!(
+            "PATP object\ncode 00\nreloc 0 nibble".parse::<Object>(),
+            Err(ObjectError::BadInput(3))
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::Path;
+
+use super::{ParseError, Span, Warning};
+
+impl ParseError {
+    //where the error is in the source, if it's anywhere in particular
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ParseError::BadInput(span)
+            | ParseError::MissingOperandError(span)
+            | ParseError::UnexpectedSymbol(span)
+            | ParseError::InvalidOpcode(span)
+            | ParseError::Unknown(span)
+            | ParseError::InvalidSymbol(_, span)
+            | ParseError::OperandOverflow(span)
+            | ParseError::OverlappingAddress(_, span, _)
+            | ParseError::SymbolRedefined(_, span, _)
+            | ParseError::ReservedName(_, span)
+            | ParseError::CircularDefinition(_, span)
+            | ParseError::UnterminatedMacro(_, span)
+            | ParseError::MacroArguments(_, _, _, span)
+            | ParseError::IncludeError(span, _, _)
+            | ParseError::CircularInclude(span, _)
+            | ParseError::ExternalSymbol(_, span) => Some(span),
+            ParseError::InMacro(_, _, error) => error.span(),
+            ParseError::ProgramTooLong(_) => None,
+        }
+    }
+
+    //renders the error the same way rustc does, with the line it's on and the problem underlined
+    //source and path are the same as were given to the parser, anything in an included file is read back from disk
+    pub fn render(&self, source: &str, path: impl AsRef<Path>) -> String {
+        let path = path.as_ref();
+        let line = |span: &Span| source_line(span, source, path);
+
+        match self {
+            //the error itself goes first, then where the macro was used
+            ParseError::InMacro(name, invocation, error) => {
+                let mut rendered = error.render(source, path);
+                rendered.push_str(&format!("note: in macro {}, used here\n", name));
+                rendered.push_str(&snippet(invocation, line(invocation).as_deref()));
+                rendered
+            }
+            _ => {
+                let mut rendered = format!("{}\n", self);
+                if let Some(span) = self.span() {
+                    rendered.push_str(&snippet(span, line(span).as_deref()));
+                }
+                rendered
+            }
+        }
+    }
+}
+
+impl Warning {
+    //renders the warning the same way as errors, with a note on how to turn it off for that line
+    pub fn render(&self, source: &str, path: impl AsRef<Path>) -> String {
+        let span = self.span();
+        format!(
+            "{}\n{}{} = note: silence this with ; allow({}) on the line\n",
+            self,
+            snippet(span, source_line(span, source, path.as_ref()).as_deref()),
+            " ".repeat(span.line.to_string().len()),
+            self.name()
+        )
+    }
+}
+
+//finds the line a span is on, either in the source given or in whichever included file it's from
+fn source_line(span: &Span, source: &str, path: &Path) -> Option<String> {
+    let file = if span.file == path.display().to_string() {
+        source.to_owned()
+    } else {
+        fs::read_to_string(&span.file).ok()?
+    };
+    file.lines()
+        .nth(span.line.checked_sub(1)?)
+        .map(str::to_owned)
+}
+
+//renders a whole list of errors, one after another, with a count at the end
+//the first line is left for whoever prints it to put "error: " in front of, same as the rest
+pub fn render_errors(errors: &[ParseError], source: &str, path: impl AsRef<Path>) -> String {
+    let rendered: Vec<String> = errors
+        .iter()
+        .map(|error| error.render(source, path.as_ref()))
+        .collect();
+    join_errors(&rendered)
+}
+
+//joins up errors that have already been rendered, in the same way as render_errors
+pub(crate) fn join_errors(rendered: &[String]) -> String {
+    format!(
+        "{}\nerror: aborting due to {} previous error{}",
+        rendered.join("\nerror: "),
+        rendered.len(),
+        if rendered.len() == 1 { "" } else { "s" }
+    )
+}
+
+//  --> file:line:column
+//   |
+// 3 | ADD 40
+//   |     ^^
+fn snippet(span: &Span, text: Option<&str>) -> String {
+    let gutter = " ".repeat(span.line.to_string().len());
+    let mut snippet = format!("{}--> {}\n", gutter, span);
+    if let Some(text) = text {
+        //columns count bytes, so the indent comes from the characters before the span rather than the column itself
+        //keep any tabs before the span so the carets still line up under it
+        let start = span.column.saturating_sub(1).min(text.len());
+        let before = text.get(..start).unwrap_or(text);
+        let indent: String = before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = text
+            .get(start..start + span.len)
+            .map_or(span.len, |spanned| spanned.chars().count());
+        snippet.push_str(&format!(
+            "{} |\n{} | {}\n{} | {}{}\n",
+            gutter,
+            span.line,
+            text.trim_end(),
+            gutter,
+            indent,
+            "^".repeat(carets)
+        ));
+    }
+    snippet
+}
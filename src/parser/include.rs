@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nom::{
+    bytes::complete::{is_not, tag_no_case},
+    character::complete::{char, space1},
+    error::VerboseError,
+    sequence::{delimited, preceded},
+    Finish, IResult, Offset,
+};
+
+use super::{end_of_line, ParseError, SourceLine, Span};
+
+//a line of source that needs parsing, along with where it came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Source {
+    //the whole line, minus any whitespace either side
+    pub(super) span: Span,
+    //the line exactly as it was written, so columns line up
+    pub(super) text: String,
+    //included lines are listed as the INCLUDE in the top level file, so the source map always points into that
+    pub(super) listed: SourceLine,
+}
+
+//read the lines of a file, pulling the lines of any files it includes in where they're included
+//blank and comment-only lines are dropped, they don't need parsing
+//any includes that can't be read are left out
+pub(super) fn read_source(file: &str, path: &Path, errors: &mut Vec<ParseError>) -> Vec<Source> {
+    //programs that didn't come from a file can't be included by anything, so don't need tracking
+    let mut including = fs::canonicalize(path).into_iter().collect();
+    let mut source = Vec::new();
+    read_lines(file, path, None, &mut including, &mut source, errors);
+    source
+}
+
+fn read_lines(
+    file: &str,
+    path: &Path,
+    included_from: Option<&SourceLine>,
+    including: &mut Vec<PathBuf>,
+    source: &mut Vec<Source>,
+    errors: &mut Vec<ParseError>,
+) {
+    let lines = file
+        .lines()
+        .enumerate() //get the real line numbers before anything is removed
+        .filter(|(_, l)| !(l.trim().starts_with(';') || l.trim().is_empty())); //remove empty or comment-only lines
+
+    for (line_no, text) in lines {
+        let trimmed = text.trim();
+        let span = Span {
+            file: path.display().to_string(),
+            line: line_no + 1,
+            column: text.offset(trimmed) + 1,
+            len: trimmed.len(),
+        };
+        let listed = included_from.cloned().unwrap_or_else(|| SourceLine {
+            number: line_no + 1,
+            text: trimmed.to_owned(),
+        });
+
+        let name = match include(trimmed).finish() {
+            Ok((_, name)) => name,
+            Err(_) => {
+                source.push(Source {
+                    span,
+                    text: text.to_owned(),
+                    listed,
+                });
+                continue;
+            }
+        };
+
+        //included files are relative to the file including them
+        let target = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+        match read_include(&target, &span, including) {
+            Ok((canonical, contents)) => {
+                including.push(canonical);
+                read_lines(&contents, &target, Some(&listed), including, source, errors);
+                including.pop();
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+}
+
+//read an included file, as long as it isn't already being included
+fn read_include(
+    target: &Path,
+    span: &Span,
+    including: &[PathBuf],
+) -> Result<(PathBuf, String), ParseError> {
+    let error = |e: std::io::Error| {
+        ParseError::IncludeError(span.clone(), target.display().to_string(), e.to_string())
+    };
+    let canonical = fs::canonicalize(target).map_err(error)?;
+    if including.contains(&canonical) {
+        return Err(ParseError::CircularInclude(
+            span.clone(),
+            target.display().to_string(),
+        ));
+    }
+    let contents = fs::read_to_string(target).map_err(error)?;
+    Ok((canonical, contents))
+}
+
+impl Source {
+    //the span of part of this line, which has to have been sliced out of its text
+    pub(super) fn span_of(&self, part: &str) -> Span {
+        Span {
+            column: self.text.offset(part) + 1,
+            len: part.len().max(1),
+            ..self.span.clone()
+        }
+    }
+}
+
+//INCLUDE "file.patp"
+fn include(i: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (i, name) = preceded(
+        preceded(tag_no_case("INCLUDE"), space1),
+        delimited(char('"'), is_not("\""), char('"')),
+    )(i)?;
+    let (i, _) = end_of_line(i)?;
+    Ok((i, name))
+}
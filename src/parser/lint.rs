@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use super::symbols::Base;
+use super::{Line, Operand, ParseOptions, Span};
+use crate::instruction::Instruction;
+
+//things that are allowed, but probably aren't what was meant
+//any of them can be turned off for a line with a comment like ; allow(unused_label)
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    #[error("Unreachable code: nothing can get here")]
+    Unreachable(Span),
+
+    #[error("Label {0} is never used")]
+    UnusedLabel(String, Span),
+
+    #[error(
+        "BNZ straight after LOAD: LOAD doesn't set Z, so this branches on whatever set it before"
+    )]
+    BranchAfterLoad(Span),
+
+    #[error("STORE overwrites the instruction at address {0}")]
+    SelfModifying(u8, Span),
+
+    #[error("Execution can run off the end of the program into zeroed memory, which is all CLEAR")]
+    FallsOffEnd(Span),
+}
+
+impl Warning {
+    //the line the warning is about
+    pub fn span(&self) -> &Span {
+        match self {
+            Warning::Unreachable(span)
+            | Warning::UnusedLabel(_, span)
+            | Warning::BranchAfterLoad(span)
+            | Warning::SelfModifying(_, span)
+            | Warning::FallsOffEnd(span) => span,
+        }
+    }
+
+    //what to put in an allow() comment to turn the warning off
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::Unreachable(_) => "unreachable",
+            Warning::UnusedLabel(..) => "unused_label",
+            Warning::BranchAfterLoad(_) => "bnz_after_load",
+            Warning::SelfModifying(..) => "self_modifying",
+            Warning::FallsOffEnd(_) => "falls_off_end",
+        }
+    }
+}
+
+//look over an assembled program for anything suspicious
+//lines, sizes and addresses are as laid out by the parser, and the program has to have assembled without errors
+//bases are what each address's operand is relative to, and entries are anywhere other modules can jump in
+pub(super) fn lint(
+    lines: &[Line],
+    sizes: &[usize],
+    addresses: &[usize],
+    instructions: &[Instruction],
+    bases: &[Base],
+    entries: &[usize],
+    options: &ParseOptions,
+) -> Vec<Warning> {
+    let len = instructions.len();
+
+    //which line each address came from. anything not from a line is a gap left as zeros
+    let mut owners: Vec<Option<&Line>> = vec![None; len];
+    for ((line, size), address) in lines.iter().zip(sizes).zip(addresses) {
+        //lines that take up nothing can be past the end, after an ORG
+        if *size == 0 {
+            continue;
+        }
+        for owner in &mut owners[*address..*address + size] {
+            *owner = Some(line);
+        }
+    }
+    let code = |address: usize| {
+        owners
+            .get(address)
+            .copied()
+            .flatten()
+            .filter(|l| l.is_code())
+    };
+    let zeroed = |address: usize| {
+        owners
+            .get(address)
+            .copied()
+            .flatten()
+            .is_none_or(|l| matches!(l.opcode.as_str(), "DS" | ".space"))
+    };
+
+    let mut warnings = Vec::new();
+
+    //follow control flow from the start, stopping anywhere that would run into zeros
+    //jumps to externals leave the module, so there's nothing to follow
+    let external = |address: usize| matches!(bases[address], Base::External(_));
+    let mut reachable = vec![false; len];
+    let mut pending: Vec<usize> = std::iter::once(0)
+        .chain(entries.iter().copied())
+        .filter(|address| *address < len)
+        .collect();
+    while let Some(address) = pending.pop() {
+        if reachable[address] {
+            continue;
+        }
+        reachable[address] = true;
+        let next = (address + 1) % 32;
+        let successors = match instructions[address] {
+            Instruction::Clear(op) if op != 0 => vec![],
+            Instruction::Jump(_) if external(address) => vec![],
+            Instruction::Bnz(_) if external(address) => vec![next],
+            Instruction::Jump(target) => vec![target as usize],
+            Instruction::Bnz(target) => vec![target as usize, next],
+            _ => vec![next],
+        };
+        for successor in successors {
+            if !zeroed(successor) {
+                pending.push(successor);
+            } else if let Some(line) = owners[address] {
+                warnings.push((line, Warning::FallsOffEnd(line.span.clone())));
+            }
+        }
+    }
+
+    //only the start of each block of unreachable code is worth pointing out
+    for address in 0..len {
+        if let Some(line) = code(address) {
+            let follows_unreachable =
+                address > 0 && code(address - 1).is_some() && !reachable[address - 1];
+            if !reachable[address] && !follows_unreachable {
+                warnings.push((line, Warning::Unreachable(line.span.clone())));
+            }
+        }
+    }
+
+    for (address, instruction) in instructions.iter().enumerate() {
+        let line = match code(address) {
+            Some(line) => line,
+            None => continue,
+        };
+        match *instruction {
+            Instruction::Bnz(_) if address > 0 => {
+                if let (Some(_), Instruction::Load(_)) =
+                    (code(address - 1), instructions[address - 1])
+                {
+                    warnings.push((line, Warning::BranchAfterLoad(line.span.clone())));
+                }
+            }
+            Instruction::Store(target) if !external(address) && code(target as usize).is_some() => {
+                warnings.push((line, Warning::SelfModifying(target, line.span.clone())));
+            }
+            _ => (),
+        }
+    }
+
+    //constants count as using a label too
+    let used: HashSet<String> = lines
+        .iter()
+        .flat_map(|line| &line.operands)
+        .filter_map(|(operand, _)| match operand {
+            Operand::Label(name, _) => Some(options.symbol_key(name)),
+            Operand::Number(_) => None,
+        })
+        .collect();
+    for line in lines.iter().filter(|line| !line.is_constant()) {
+        if let Some(label) = line
+            .label
+            .as_deref()
+            .filter(|label| !used.contains(&options.symbol_key(label)))
+        {
+            //labels in macros are renamed for each use, but should be reported by the name they were written with
+            let name = label.split('@').next().unwrap_or(label);
+            warnings.push((
+                line,
+                Warning::UnusedLabel(name.to_owned(), line.span.clone()),
+            ));
+        }
+    }
+
+    let mut warnings: Vec<Warning> = warnings
+        .into_iter()
+        .filter(|(line, warning)| !line.allow.iter().any(|name| name == warning.name()))
+        .map(|(_, warning)| warning)
+        .collect();
+    //macros can produce the same warning for the same line more than once
+    warnings.sort_by(|a, b| a.span().cmp(b.span()).then_with(|| a.name().cmp(b.name())));
+    warnings.dedup();
+    warnings
+}
+
+//warnings can be turned off for a line with a comment containing allow(name, name...)
+pub(super) fn allowed(comment: &str) -> Vec<String> {
+    comment
+        .split_once("allow(")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(names, _)| {
+            names
+                .split(',')
+                .map(|name| name.trim().to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+
+use nom::{
+    bytes::complete::{tag, tag_no_case},
+    character::complete::{space0, space1},
+    combinator::{map, opt},
+    error::VerboseError,
+    multi::separated_list1,
+    sequence::{delimited, pair, preceded, tuple},
+    IResult,
+};
+
+use super::{end_of_line, identifier, label, operand, Line, Operand, ParseOptions, Span};
+
+//a macro definition: the names of its parameters, the already parsed lines of its body, and where it was defined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Macro {
+    pub(super) params: Vec<String>,
+    pub(super) body: Vec<Line>,
+    pub(super) span: Span,
+}
+
+impl Macro {
+    //expands the macro with the given arguments
+    //labels defined inside the body get a suffix unique to this expansion, so a macro with a loop can be used twice
+    pub(super) fn expand(
+        &self,
+        name: &str,
+        args: &[Operand],
+        invocation: &Line,
+        expansion: usize,
+        options: &ParseOptions,
+    ) -> Vec<Line> {
+        let params: HashMap<String, &Operand> = self
+            .params
+            .iter()
+            .map(|param| options.symbol_key(param))
+            .zip(args)
+            .collect();
+        let locals: HashSet<String> = self
+            .body
+            .iter()
+            .filter_map(|line| line.label.as_deref())
+            .map(|label| options.symbol_key(label))
+            .collect();
+        //@ can't appear in a label in the source, so these can't clash with anything
+        let local = |label: &str| format!("{}@{}", label, expansion);
+
+        self.body
+            .iter()
+            .map(|line| {
+                let operands = line
+                    .operands
+                    .iter()
+                    .map(|(operand, span)| {
+                        let operand = match operand {
+                            Operand::Label(l, offset) => match params.get(&options.symbol_key(l)) {
+                                Some(Operand::Number(n)) => {
+                                    Operand::Number(n.saturating_add(*offset))
+                                }
+                                Some(Operand::Label(arg, arg_offset)) => {
+                                    Operand::Label(arg.clone(), arg_offset.saturating_add(*offset))
+                                }
+                                None if locals.contains(&options.symbol_key(l)) => {
+                                    Operand::Label(local(l), *offset)
+                                }
+                                None => operand.clone(),
+                            },
+                            Operand::Number(_) => operand.clone(),
+                        };
+                        (operand, span.clone())
+                    })
+                    .collect();
+                Line {
+                    label: line.label.as_deref().map(local),
+                    operands,
+                    source: invocation.source.clone(),
+                    //anything turned off where the macro is used is turned off for all of it
+                    allow: [&line.allow[..], &invocation.allow[..]].concat(),
+                    expansion: Some((name.to_owned(), invocation.span.clone())),
+                    ..line.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+//the first line of a macro definition, MACRO name param, param...
+pub(super) fn macro_header(i: &str) -> IResult<&str, (&str, Vec<&str>), VerboseError<&str>> {
+    let params = separated_list1(delimited(space0, tag(","), space0), identifier);
+    let (i, header) = preceded(
+        pair(tag_no_case("MACRO"), space1),
+        pair(
+            identifier,
+            map(opt(preceded(space1, params)), Option::unwrap_or_default),
+        ),
+    )(i.trim())?;
+    let (i, _) = end_of_line(i)?;
+    Ok((i, header))
+}
+
+//the last line of a macro definition
+pub(super) fn macro_end(i: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (i, end) = tag_no_case("ENDM")(i.trim())?;
+    let (i, _) = end_of_line(i)?;
+    Ok((i, end))
+}
+
+//using a macro, label: name arg, arg... ; comment
+#[allow(clippy::type_complexity)]
+pub(super) fn invocation(
+    i: &str,
+) -> IResult<&str, (Option<&str>, &str, Vec<Operand>, &str), VerboseError<&str>> {
+    let args = separated_list1(delimited(space0, tag(","), space0), operand);
+    let (i, (label, name, args)) = tuple((
+        label,
+        identifier,
+        map(opt(preceded(space1, args)), Option::unwrap_or_default),
+    ))(i.trim())?;
+    let (i, comment) = end_of_line(i)?;
+    Ok((i, (label, name, args, comment)))
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use nom::{
+    bytes::complete::tag,
+    character::complete::space0,
+    combinator::opt,
+    error::VerboseError,
+    sequence::{delimited, preceded, separated_pair},
+    Finish, IResult, Offset,
+};
+
+use super::{end_of_line, identifier, ParseError, ParseOptions, Span};
+use crate::instruction::{mnemonic, Mnemonic};
+
+impl ParseOptions {
+    //adds the aliases from a config file, which has one per line as ALIAS = MNEMONIC, and ; comments
+    //aliases can't take the name of anything that already exists, and have to name a built in mnemonic
+    pub fn add_aliases(
+        &mut self,
+        config: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Vec<ParseError>> {
+        let file = path.as_ref().display().to_string();
+        let mut errors = Vec::new();
+
+        for (number, text) in config.lines().enumerate() {
+            let span = |part: &str| Span {
+                file: file.clone(),
+                line: number + 1,
+                column: text.offset(part) + 1,
+                len: part.len().max(1),
+            };
+            let (name, target) = match alias(text).finish() {
+                Ok((_, Some(alias))) => alias,
+                Ok((_, None)) => continue,
+                Err(_) => {
+                    errors.push(ParseError::BadInput(span(text.trim())));
+                    continue;
+                }
+            };
+            let statement = span(&text[text.offset(name)..text.offset(target) + target.len()]);
+
+            if let Some((_, first)) = self.aliases.get(&name.to_uppercase()) {
+                errors.push(ParseError::SymbolRedefined(
+                    name.to_owned(),
+                    statement,
+                    first.clone(),
+                ));
+            } else if self.is_reserved(name) {
+                errors.push(ParseError::ReservedName(name.to_owned(), statement));
+            } else if let Some(mnemonic) = mnemonic(target) {
+                self.aliases
+                    .insert(name.to_uppercase(), (mnemonic.name, statement));
+            } else {
+                errors.push(ParseError::InvalidOpcode(span(target)));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    //looks up a mnemonic or one of the aliases for one, whatever case it's written in
+    pub(super) fn mnemonic(&self, name: &str) -> Option<&'static Mnemonic> {
+        mnemonic(name).or_else(|| {
+            self.aliases
+                .get(&name.to_uppercase())
+                .and_then(|(target, _)| mnemonic(target))
+        })
+    }
+}
+
+//ALIAS = MNEMONIC ; comment, or a line with nothing but a comment on it
+fn alias(i: &str) -> IResult<&str, Option<(&str, &str)>, VerboseError<&str>> {
+    let (i, alias) = preceded(
+        space0,
+        opt(separated_pair(
+            identifier,
+            delimited(space0, tag("="), space0),
+            identifier,
+        )),
+    )(i)?;
+    let (i, _) = end_of_line(i)?;
+    Ok((i, alias))
+}
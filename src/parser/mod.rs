@@ -1,212 +1,947 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
 
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alphanumeric1, space0, space1, u8},
-    combinator::{flat_map, opt, rest, success},
-    error::{convert_error, VerboseError},
-    sequence::{pair, preceded, separated_pair, terminated},
+    bytes::complete::{is_a, tag, tag_no_case},
+    character::complete::{
+        alpha1, alphanumeric1, char, digit1, hex_digit1, none_of, one_of, space0, space1,
+    },
+    combinator::{consumed, eof, map, map_opt, opt, recognize, rest, value},
+    error::VerboseError,
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     Finish, IResult,
 };
 use thiserror::Error;
 
-use crate::instruction::Instruction;
-
+pub(crate) use self::diagnostic::join_errors;
+pub use self::diagnostic::render_errors;
+use self::include::{read_source, Source};
+pub use self::lint::Warning;
+use self::lint::{allowed, lint};
+use self::macros::{invocation, macro_end, macro_header, Macro};
+use self::symbols::{Base, SymbolTable};
+use crate::instruction::{mnemonic, Instruction, Mnemonic};
+use crate::object::{Export, Field, Object, Reference, Relocation};
+
+mod diagnostic;
+mod include;
+mod lint;
+mod macros;
+mod mnemonics;
+mod symbols;
 mod test;
 
 // lines have format label: opcode operand; comment
+// data directives can have a comma separated list of operands
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct Line {
-    number: usize,
+    span: Span,
+    //the line this one is listed as in the source map
+    source: SourceLine,
     label: Option<String>,
     opcode: String,
-    operand: Option<Operand>,
+    operands: Vec<(Operand, Span)>,
+    //the warnings turned off for this line
+    allow: Vec<String>,
+    //the macro this line came from and the line it was used on, if it's part of an expansion
+    expansion: Option<(String, Span)>,
 }
 
-//operand is either a label or a number
+//operand is either a number or a label, with an offset for things like label+1
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 enum Operand {
-    Number(u8),
-    Label(String),
+    Number(i64),
+    Label(String, i64),
+}
+
+//where something is in the source: which file it's in, the line and column it starts at (counting from 1),
+//and how many characters long it is. programs that didn't come from a file have an empty file name
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+//a line of source text, kept so we can map addresses back to where they came from
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLine {
+    pub number: usize,
+    pub text: String,
+}
+
+//maps each address in the assembled program to the source line it was assembled from
+pub type SourceMap = BTreeMap<u8, SourceLine>;
+
+//settings that change what the assembler accepts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    //symbols mean the same whatever case they're written in, so Loop is loop
+    //mnemonics and directives can always be written in any case
+    pub case_insensitive: bool,
+    //extra names for mnemonics, in upper case, with the mnemonic they stand for and where they were given
+    aliases: HashMap<String, (&'static str, Span)>,
+}
+
+//the directives, which like the mnemonics can't be used as names for anything else
+const DIRECTIVES: &[&str] = &[
+    "DB", "DS", "ORG", "EQU", "MACRO", "ENDM", "INCLUDE", "GLOBAL", "EXTERN",
+];
+
+impl ParseOptions {
+    //what a symbol is looked up by, so that different spellings of the same name find the same thing
+    fn symbol_key(&self, name: &str) -> String {
+        if self.case_insensitive {
+            name.to_lowercase()
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn is_reserved(&self, name: &str) -> bool {
+        self.mnemonic(name).is_some()
+            || DIRECTIVES
+                .iter()
+                .any(|directive| directive.eq_ignore_ascii_case(name))
+    }
+}
+
+//the extra information about a program that the debugger needs, on top of the instructions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub source_map: SourceMap,
+    //where the line each address came from is actually written, which can be in an included file or a macro,
+    //whereas the source map always points into the file that was assembled
+    pub locations: BTreeMap<u8, Span>,
+    pub symbols: HashMap<String, usize>,
+    pub warnings: Vec<Warning>,
+    //what the program was assembled with, so symbols are looked up the same way afterwards
+    pub options: ParseOptions,
+}
+
+impl DebugInfo {
+    //the value of a symbol, found by any spelling of it the program could have used
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        let key = self.options.symbol_key(name);
+        self.symbols
+            .iter()
+            .find(|(symbol, _)| self.options.symbol_key(symbol) == key)
+            .map(|(_, value)| *value)
+    }
 }
 
 //various errors that may occur at different stages of parsing
+//they say where they happened, and can be rendered with the source to show exactly where
 #[derive(Error, Debug, PartialEq)]
 pub enum ParseError {
-    #[error("Unrecognised input on line {0}. Parser trace:\n{1}")]
-    BadInput(usize, String),
+    #[error("Unrecognised input")]
+    BadInput(Span),
 
-    #[error("Could not parse operand on line {0}")]
-    MissingOperandError(usize),
+    #[error("Could not parse operand")]
+    MissingOperandError(Span),
 
-    #[error("Unexpected symbol found on line {0}")]
+    #[error("Unexpected symbol found")]
     #[allow(dead_code)]
-    UnexpectedSymbol(usize),
+    UnexpectedSymbol(Span),
 
-    #[error("Invalid opcode on line {0}")]
-    InvalidOpcode(usize),
+    #[error("Invalid opcode")]
+    InvalidOpcode(Span),
 
-    #[error("Unknown parse error on line {0}")]
+    #[error("Unknown parse error")]
     #[allow(dead_code)]
-    Unknown(usize),
+    Unknown(Span),
 
     #[error("Program is too long: contains {0} instructions (max 32)")]
     ProgramTooLong(usize),
 
-    #[error("Unknown symbol {0} on line {1}")]
-    InvalidSymbol(String, usize),
+    #[error("Unknown symbol {0}")]
+    InvalidSymbol(String, Span),
+
+    #[error("Invalid operand: operand is out of range")]
+    OperandOverflow(Span),
+
+    #[error("Address {0} is already used by the line at {2}")]
+    OverlappingAddress(usize, Span, Span),
 
-    #[error("Invalid operand on line {0}: operand is greater than 32")]
-    OperandOverflow(usize),
+    #[error("Symbol {0} is already defined at {2}")]
+    SymbolRedefined(String, Span, Span),
+
+    #[error("{0} is an instruction or directive, so can't be used as a name")]
+    ReservedName(String, Span),
+
+    #[error("Constant {0} is defined in terms of itself")]
+    CircularDefinition(String, Span),
+
+    #[error("Macro {0} has no ENDM")]
+    UnterminatedMacro(String, Span),
+
+    #[error("Macro {0} takes {1} arguments but was given {2}")]
+    MacroArguments(String, usize, usize, Span),
+
+    #[error("{2}, in macro {0} used at {1}")]
+    InMacro(String, Span, Box<ParseError>),
+
+    #[error("Could not include {1}: {2}")]
+    IncludeError(Span, String, String),
+
+    #[error("{1} includes itself")]
+    CircularInclude(Span, String),
+
+    #[error("Symbol {0} is external, so it only has a value once the program is linked")]
+    ExternalSymbol(String, Span),
+}
+
+//file:line:column, or just line:column if there's no file
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 //parse a file, retunrning a vec of all the instructions
-pub fn parse_file(file: &str) -> Result<Vec<Instruction>, ParseError> {
-    let lines = file
-        .lines()
-        .map(|l| l.trim()) // remove whitespace from each line
-        .filter(|l| !(l.starts_with(';') || l.is_empty())) //remove empty or comment-only lines
-        .enumerate() //get line numbers
-        .map(|(line_no, line)| {
-            //map the parser over every line
-            instruction(line, line_no)
-                .finish() //convert the errors, TODO: add more context to parsers and make this less basic
-                .map_err(|error| ParseError::BadInput(line_no, convert_error(line, error))) //map nom errors into our errors
-                .map(|x| x.1) //drop the remaining input, we only want the line
-        });
-    let lines: Vec<Line> = lines.collect::<Result<_, _>>()?; //collect into result
+//if anything's wrong, every error that can be found is returned, not just the first
+pub fn parse_file(file: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    parse_file_with_source(file).map(|(instructions, _)| instructions)
+}
+
+//parse a file, also returning the symbol table and a map from each address back to the source line it came from
+//any files it includes are looked for relative to the current directory
+pub fn parse_file_with_source(
+    file: &str,
+) -> Result<(Vec<Instruction>, DebugInfo), Vec<ParseError>> {
+    parse_file_from(file, "")
+}
+
+//parse a file that was read from the given path, so any files it includes are looked for next to it
+//errors say which file they're in as well as the line
+pub fn parse_file_from(
+    file: &str,
+    path: impl AsRef<Path>,
+) -> Result<(Vec<Instruction>, DebugInfo), Vec<ParseError>> {
+    parse_file_with_options(file, path, &ParseOptions::default())
+}
+
+//same as parse_file_from, with options to change what's accepted
+pub fn parse_file_with_options(
+    file: &str,
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(Vec<Instruction>, DebugInfo), Vec<ParseError>> {
+    parse_program(file, path.as_ref(), options, false)
+        .map(|program| (program.instructions, program.debug_info))
+}
+
+//parse a file as one module of a program, which can use symbols declared with EXTERN from other modules
+//and shares any declared with GLOBAL. the object it assembles to is laid out from address 0, and moved when it's linked
+pub fn parse_object(
+    file: &str,
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(Object, DebugInfo), Vec<ParseError>> {
+    let program = parse_program(file, path.as_ref(), options, true)?;
+
+    //anything that made it through the parser has already been range checked
+    let code = program
+        .instructions
+        .iter()
+        .map(|i| i.assemble().expect("assembled out of range operand"))
+        .collect();
+    let mut object = Object {
+        code,
+        exports: program
+            .exports
+            .into_iter()
+            .map(|(name, value, base)| Export {
+                name,
+                value,
+                relocatable: base == Base::Relocatable,
+            })
+            .collect(),
+        ..Object::default()
+    };
+    for (address, field, base) in program.fixups {
+        let offset = address as u8;
+        match base {
+            Base::Absolute => (),
+            Base::Relocatable => object.relocations.push(Relocation { offset, field }),
+            Base::External(symbol) => object.references.push(Reference {
+                offset,
+                field,
+                symbol,
+            }),
+        }
+    }
+    Ok((object, program.debug_info))
+}
+
+//everything the parser works out about a program
+struct Program {
+    instructions: Vec<Instruction>,
+    debug_info: DebugInfo,
+    //every address that holds a symbol's value, with the part of the byte it's in and what it's relative to
+    fixups: Vec<(usize, Field, Base)>,
+    //the symbols named by GLOBAL, with their values
+    exports: Vec<(String, u8, Base)>,
+}
+
+//parse a program, either on its own or as an object that allows externals
+fn parse_program(
+    file: &str,
+    path: &Path,
+    options: &ParseOptions,
+    object: bool,
+) -> Result<Program, Vec<ParseError>> {
+    //each stage carries on past any errors it finds, leaving out whatever was wrong,
+    //so that everything else can still be checked
+    let mut errors = Vec::new();
+
+    let source = read_source(file, path, &mut errors);
+    let lines = expand_macros(&source, options, &mut errors);
+    //any lines that couldn't be parsed might have defined symbols, so unknown symbols aren't worth reporting
+    let broken = !errors.is_empty();
+
+    let mut symbols = SymbolTable::new(&lines, options, &mut errors);
+
+    //ORG and DS have to be worked out before anything can be laid out, so they can only use constants
+    let layout: Vec<(Option<usize>, usize)> = lines
+        .iter()
+        .map(|line| {
+            line.layout(&symbols).unwrap_or_else(|e| {
+                errors.push(line.in_context(e));
+                (None, 0)
+            })
+        })
+        .collect();
+
+    //lay the lines out in memory one after the other, some of them take up more than one byte
+    //ORG moves where the next line goes
+    let addresses: Vec<usize> = layout
+        .iter()
+        .scan(0, |address, (origin, size)| {
+            if let Some(origin) = origin {
+                *address = *origin;
+            }
+            let start = *address;
+            *address += size;
+            Some(start)
+        })
+        .collect();
+    //only lines that take up memory count, so an ORG at the end doesn't pad the program out
+    let size = layout
+        .iter()
+        .zip(&addresses)
+        .filter(|((_, size), _)| *size > 0)
+        .map(|((_, size), address)| address + size)
+        .max()
+        .unwrap_or(0);
 
     //if program too long, then yeet
-    if lines.len() >= 32 {
-        return Err(ParseError::ProgramTooLong(lines.len()));
+    if size > 32 {
+        errors.push(ParseError::ProgramTooLong(size));
+    }
+
+    //make sure no two lines want the same address
+    let mut owners: Vec<Option<&Span>> = vec![None; size];
+    for ((line, (_, size)), address) in lines.iter().zip(&layout).zip(&addresses) {
+        //lines that take up nothing can be past the end, after an ORG
+        if *size == 0 {
+            continue;
+        }
+        for owner in &mut owners[*address..*address + size] {
+            match owner {
+                Some(other) => errors.push(line.in_context(ParseError::OverlappingAddress(
+                    *address,
+                    line.span.clone(),
+                    (*other).clone(),
+                ))),
+                None => *owner = Some(&line.span),
+            }
+        }
+    }
+
+    //labels point at the address of the line they're on
+    for (line, address) in lines.iter().zip(&addresses) {
+        if let (Some(label), false) = (&line.label, line.is_constant()) {
+            if let Err(e) = symbols.add_label(label, *address, &line.span) {
+                errors.push(line.in_context(e));
+            }
+        }
     }
 
-    //build symbol table
-    let symbols: HashMap<String, usize> = lines
+    //every address a line takes up maps back to it
+    //anything from a macro or another file maps back to where it was used in this one
+    let source_map = lines
+        .iter()
+        .zip(&layout)
+        .zip(&addresses)
+        .flat_map(|((line, (_, size)), address)| {
+            (*address..*address + size).map(move |a| (a as u8, line.source.clone()))
+        })
+        .collect();
+    let locations = lines
         .iter()
-        .flat_map(|line| line.label.clone().map(|l| (l, line.number)))
+        .zip(&layout)
+        .zip(&addresses)
+        .flat_map(|((line, (_, size)), address)| {
+            (*address..*address + size).map(move |a| (a as u8, line.span.clone()))
+        })
         .collect();
 
-    //process lines into instruction
-    let instructions = lines.into_iter().map(|line| parse_line(line, &symbols));
-    instructions.collect()
-}
+    //process lines into instructions, and put them where they belong. any gaps are left as zeros
+    let mut instructions = vec![Instruction::Clear(0); size];
+    let mut bases = vec![Base::Absolute; size];
+    for ((line, (_, size)), address) in lines.iter().zip(&layout).zip(&addresses) {
+        match parse_line(line, *size, &symbols, object) {
+            Ok(assembled) => {
+                for (i, (instruction, base)) in assembled.into_iter().enumerate() {
+                    instructions[address + i] = instruction;
+                    bases[address + i] = base;
+                }
+            }
+            Err(e) => errors.push(line.in_context(e)),
+        }
+    }
+
+    let resolved = symbols.resolve_all(&mut errors);
 
-//parse a single line (with symbol table, converting any symbols), returning the parsed instruction
-fn parse_line(line: Line, symbols: &HashMap<String, usize>) -> Result<Instruction, ParseError> {
-    let operand = line
-        .operand
-        .map(|operand| match operand {
-            Operand::Number(x) => Ok(x),
-            Operand::Label(l) => {
-                let n = symbols
-                    .get(&l)
-                    .ok_or(ParseError::InvalidSymbol(l, line.number))?;
+    if broken {
+        errors.retain(|e| !e.is_unknown_symbol());
+    }
+    if !errors.is_empty() {
+        //in the order they appear in the source, which also puts any duplicates next to each other
+        errors.sort_by_key(|e| match e {
+            ParseError::InMacro(_, invocation, error) => {
+                (false, Some(invocation.clone()), error.span().cloned())
+            }
+            e => (e.span().is_none(), e.span().cloned(), None),
+        });
+        errors.dedup();
+        return Err(errors);
+    }
 
-                (*n).try_into()
-                    .map_err(|_| ParseError::OperandOverflow(line.number))
+    //the exports have all been checked by parse_line, so they can't fail now
+    let mut exports: Vec<(String, u8, Base)> = Vec::new();
+    for (operand, span) in lines
+        .iter()
+        .filter(|line| matches!(line.opcode.as_str(), "GLOBAL" | ".global"))
+        .flat_map(|line| &line.operands)
+    {
+        if let (Operand::Label(name, _), Ok((value, base))) =
+            (operand, symbols.resolve(operand, span))
+        {
+            if !exports.iter().any(|(other, _, _)| other == name) {
+                exports.push((name.clone(), value as u8, base));
             }
+        }
+    }
+
+    let fixups = lines
+        .iter()
+        .zip(&layout)
+        .zip(&addresses)
+        .flat_map(|((line, (_, size)), address)| {
+            let field = if line.is_code() {
+                Field::Operand
+            } else {
+                Field::Byte
+            };
+            (*address..address + size).map(move |a| (a, field))
         })
-        .transpose()?;
+        .filter(|(a, _)| bases[*a] != Base::Absolute)
+        .map(|(a, field)| (a, field, bases[a].clone()))
+        .collect();
 
-    if let Some(operand) = operand {
-        if operand >= 32 {
-            return Err(ParseError::OperandOverflow(line.number));
+    //exported labels are where other modules can jump in, so everything from them is reachable too
+    let entries: Vec<usize> = exports
+        .iter()
+        .filter(|(_, _, base)| *base == Base::Relocatable)
+        .map(|(_, value, _)| *value as usize)
+        .collect();
+    let sizes: Vec<usize> = layout.iter().map(|(_, size)| *size).collect();
+    let warnings = lint(
+        &lines,
+        &sizes,
+        &addresses,
+        &instructions,
+        &bases,
+        &entries,
+        options,
+    );
+
+    Ok(Program {
+        instructions,
+        debug_info: DebugInfo {
+            source_map,
+            locations,
+            symbols: resolved,
+            warnings,
+            options: options.clone(),
+        },
+        fixups,
+        exports,
+    })
+}
+
+//parse every line, collecting up macro definitions and replacing each use of one with its body
+//macros have to be defined before they're used, and can't use other macros
+//lines with errors are left out
+fn expand_macros(
+    source: &[Source],
+    options: &ParseOptions,
+    errors: &mut Vec<ParseError>,
+) -> Vec<Line> {
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut expansions = 0;
+
+    let mut source = source.iter();
+    while let Some(line) = source.next() {
+        //everything up to the ENDM is the body, which is parsed now but not assembled until it's used
+        if let Ok((_, (name, params))) = macro_header(&line.text).finish() {
+            let mut body = Vec::new();
+            loop {
+                let body_line = match source.next() {
+                    Some(body_line) => body_line,
+                    None => {
+                        errors.push(ParseError::UnterminatedMacro(
+                            name.to_owned(),
+                            line.span.clone(),
+                        ));
+                        return lines;
+                    }
+                };
+                if macro_end(&body_line.text).is_ok() {
+                    break;
+                }
+                match parse_instruction(body_line, options) {
+                    Ok(body_line) => body.push(body_line),
+                    Err(e) => errors.push(e),
+                }
+            }
+            let definition = Macro {
+                params: params.into_iter().map(str::to_owned).collect(),
+                body,
+                span: line.span.clone(),
+            };
+            if options.is_reserved(name) {
+                errors.push(ParseError::ReservedName(name.to_owned(), line.span.clone()));
+            } else if let Some(first) = macros.get(&options.symbol_key(name)) {
+                errors.push(ParseError::SymbolRedefined(
+                    name.to_owned(),
+                    line.span.clone(),
+                    first.span.clone(),
+                ));
+            } else {
+                macros.insert(options.symbol_key(name), definition);
+            }
+            continue;
         }
+
+        let error = match parse_instruction(line, options) {
+            Ok(line) => {
+                lines.push(line);
+                continue;
+            }
+            Err(error) => error,
+        };
+
+        //if it's not an instruction it might be a macro, otherwise it's just bad input
+        let (label, name, args, comment) = match invocation(&line.text).finish() {
+            Ok((_, invocation)) if macros.contains_key(&options.symbol_key(invocation.1)) => {
+                invocation
+            }
+            _ => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let definition = &macros[&options.symbol_key(name)];
+        if args.len() != definition.params.len() {
+            errors.push(ParseError::MacroArguments(
+                name.to_owned(),
+                definition.params.len(),
+                args.len(),
+                line.span.clone(),
+            ));
+            continue;
+        }
+
+        //the line the macro is used on takes up no space, it's just there to hold the label
+        let invocation = Line {
+            span: line.span.clone(),
+            source: line.listed.clone(),
+            label: label.map(str::to_owned),
+            opcode: "MACRO".to_owned(),
+            operands: vec![],
+            allow: allowed(comment),
+            expansion: None,
+        };
+        expansions += 1;
+        let expansion = definition.expand(name, &args, &invocation, expansions, options);
+        lines.push(invocation);
+        lines.extend(expansion);
     }
+    lines
+}
 
-    let instruction = match line.opcode.as_str() {
-        "CLEAR" => Instruction::Clear(0),
-        "STOP" => Instruction::Clear(1),
-        "INC" => Instruction::Inc,
-        "ADD" => Instruction::Add(operand.ok_or(ParseError::MissingOperandError(line.number))?),
-        "DEC" => Instruction::Dec,
-        "JMP" | "JUMP" => {
-            Instruction::Jump(operand.ok_or(ParseError::MissingOperandError(line.number))?)
+//run the nom parser over a line, converting the error into one of ours
+fn parse_instruction(source: &Source, options: &ParseOptions) -> Result<Line, ParseError> {
+    instruction(&source.text, source, options)
+        .finish() //convert the errors, TODO: add more context to parsers and make this less basic
+        .map_err(|error| {
+            //point at wherever the parser got furthest before giving up
+            let rest = error
+                .errors
+                .iter()
+                .map(|(rest, _)| *rest)
+                .min_by_key(|rest| rest.len())
+                .unwrap_or(&source.text);
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c == ';' || c == ',')
+                .unwrap_or(rest.len());
+            ParseError::BadInput(source.span_of(&rest[..len]))
+        }) //map nom errors into our errors
+        .map(|x| x.1) //drop the remaining input, we only want the line
+}
+
+impl ParseError {
+    //whether this is a symbol that couldn't be found, even if it was found inside a macro
+    fn is_unknown_symbol(&self) -> bool {
+        match self {
+            ParseError::InvalidSymbol(..) => true,
+            ParseError::InMacro(_, _, error) => error.is_unknown_symbol(),
+            _ => false,
         }
-        "BUZ" | "BNZ" | "BZC" | "BNE" => {
-            Instruction::Bnz(operand.ok_or(ParseError::MissingOperandError(line.number))?)
+    }
+}
+
+impl Line {
+    //errors in lines that came from a macro say which use of the macro they came from
+    fn in_context(&self, error: ParseError) -> ParseError {
+        match &self.expansion {
+            Some((name, invocation)) => {
+                ParseError::InMacro(name.clone(), invocation.clone(), Box::new(error))
+            }
+            None => error,
         }
-        "LOAD" => Instruction::Load(operand.ok_or(ParseError::MissingOperandError(line.number))?),
-        "STORE" => Instruction::Store(operand.ok_or(ParseError::MissingOperandError(line.number))?),
-        _ => return Err(ParseError::InvalidOpcode(line.number)),
+    }
+
+    //whether this line is an instruction, rather than data or a directive
+    fn is_code(&self) -> bool {
+        !matches!(
+            self.opcode.as_str(),
+            "DB" | ".byte"
+                | "DS"
+                | ".space"
+                | "ORG"
+                | ".org"
+                | "EQU"
+                | ".set"
+                | "MACRO"
+                | "GLOBAL"
+                | ".global"
+                | "EXTERN"
+                | ".extern"
+        )
+    }
+
+    //constant definitions use the label for the name of the constant
+    fn is_constant(&self) -> bool {
+        matches!(self.opcode.as_str(), "EQU" | ".set")
+    }
+
+    //where this line needs to go: the address it moves the assembler to if it's an ORG,
+    //and the number of bytes it assembles to
+    fn layout(&self, symbols: &SymbolTable) -> Result<(Option<usize>, usize), ParseError> {
+        //neither of these can go past the end of memory
+        let value = |(operand, span): &(Operand, Span)| {
+            symbols
+                .value(operand, span)?
+                .try_into()
+                .ok()
+                .filter(|v| *v <= 32)
+                .ok_or_else(|| ParseError::OperandOverflow(span.clone()))
+        };
+        let layout = match self.opcode.as_str() {
+            "DB" | ".byte" => (None, self.operands.len()),
+            "DS" | ".space" => (None, value(&self.operands[0])?),
+            "ORG" | ".org" => (Some(value(&self.operands[0])?), 0),
+            "EQU" | ".set" | "MACRO" | "GLOBAL" | ".global" | "EXTERN" | ".extern" => (None, 0),
+            _ => (None, 1),
+        };
+        Ok(layout)
+    }
+}
+
+//parse a single line (with symbol table, converting any symbols), returning the instructions it assembles to
+//and what the operand of each is relative to. most lines are a single instruction, but data directives can be any number of bytes
+//externals can only be used in an object, where they're left as just their offset until the program is linked
+fn parse_line(
+    line: &Line,
+    size: usize,
+    symbols: &SymbolTable,
+    object: bool,
+) -> Result<Vec<(Instruction, Base)>, ParseError> {
+    //anything that ends up negative or too big for a byte is out of range
+    let byte = |value: i64, span: &Span| {
+        u8::try_from(value).map_err(|_| ParseError::OperandOverflow(span.clone()))
     };
-    Ok(instruction)
+
+    //these don't produce anything, and their operands have already been dealt with
+    match line.opcode.as_str() {
+        "DS" | ".space" => return Ok(vec![(Instruction::Clear(0), Base::Absolute); size]),
+        "ORG" | ".org" | "EQU" | ".set" | "MACRO" | "EXTERN" | ".extern" => return Ok(vec![]),
+        //exports have to be something in this module that fits in a byte
+        "GLOBAL" | ".global" => {
+            for (operand, span) in &line.operands {
+                byte(symbols.value(operand, span)?, span)?;
+            }
+            return Ok(vec![]);
+        }
+        _ => (),
+    }
+
+    let operands = line
+        .operands
+        .iter()
+        .map(|(operand, span)| {
+            let (value, base) = match symbols.resolve(operand, span)? {
+                (_, Base::External(name)) if !object => {
+                    return Err(ParseError::ExternalSymbol(name, span.clone()))
+                }
+                resolved => resolved,
+            };
+            Ok((byte(value, span)?, base))
+        })
+        .collect::<Result<Vec<(u8, Base)>, _>>()?;
+
+    //data can be any byte, which is stored as whatever instruction that byte happens to be
+    match line.opcode.as_str() {
+        "DB" | ".byte" => {
+            return Ok(operands
+                .into_iter()
+                .map(|(value, base)| (Instruction::disassemble(value), base))
+                .collect())
+        }
+        _ => (),
+    }
+
+    let (operand, base) = match operands.into_iter().next() {
+        Some((operand, base)) => (Some(operand), base),
+        None => (None, Base::Absolute),
+    };
+    if let (Some(operand), Some((_, span))) = (operand, line.operands.first()) {
+        if operand >= 32 {
+            return Err(ParseError::OperandOverflow(span.clone()));
+        }
+    }
+
+    //the parser has already turned any alias into the mnemonic it stands for
+    let mnemonic = mnemonic(&line.opcode).ok_or(ParseError::InvalidOpcode(line.span.clone()))?;
+    let operand = match (mnemonic.operand, operand) {
+        (false, _) => 0,
+        (true, Some(operand)) => operand,
+        (true, None) => return Err(ParseError::MissingOperandError(line.span.clone())),
+    };
+    Ok(vec![((mnemonic.instruction)(operand), base)])
 }
 
 //parse an entire instruction
 //top-level nom parser
 //should return result with no input left
-fn instruction(i: &str, line_no: usize) -> IResult<&str, Line, VerboseError<&str>> {
+fn instruction<'a>(
+    i: &'a str,
+    source: &Source,
+    options: &ParseOptions,
+) -> IResult<&'a str, Line, VerboseError<&'a str>> {
     //trim whitespace and newlines
-    let i = i.trim();
-
-    //get the label
-    let (i, label) = label(i)?;
+    let start = i.trim();
 
-    //get the operation
-    let (i, (opcode, operand)) = ops(i)?;
+    //get the label and the operation, or a constant definition which names the constant instead of a label
+    let (i, (label, (opcode, operands))) = alt((
+        constant,
+        pair(label, alt((directives, |i| ops(i, options)))),
+    ))(start)?;
 
-    //make sure the rest of the input is either comment or whitespace
-    let (i, _) = alt((preceded(preceded(space0, tag(";")), rest), space0))(i)?;
+    //the span of the line is everything up to the comment
+    let statement = start[..start.len() - i.len()].trim_end();
+    let (i, comment) = end_of_line(i)?;
 
     Ok((
         i,
         Line {
             label: label.map(str::to_owned),
             opcode: opcode.to_owned(),
-            number: line_no,
-            operand,
+            span: source.span_of(statement),
+            source: source.listed.clone(),
+            operands: operands
+                .into_iter()
+                .map(|(text, operand)| (operand, source.span_of(text)))
+                .collect(),
+            allow: allowed(comment),
+            expansion: None,
         },
     ))
 }
 
+//make sure the rest of the input is either comment or whitespace, returning the comment
+fn end_of_line(i: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (i, comment) = alt((preceded(preceded(space0, tag(";")), rest), space0))(i)?;
+    let (i, _) = eof(i)?;
+    Ok((i, comment))
+}
+
+//a directive name, in any case, which always comes out spelt the way it's written here
+fn keyword<'a>(
+    name: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, VerboseError<&'a str>> {
+    value(name, tag_no_case(name))
+}
+
 //parse opcode-operand
-fn ops(i: &str) -> IResult<&str, (&str, Option<Operand>), VerboseError<&str>> {
-    //no operand
-    let clear = pair(tag("CLEAR"), success(None));
-    let inc = pair(tag("INC"), success(None));
-    let dec = pair(tag("DEC"), success(None));
-    let stop = pair(tag("STOP"), success(None));
-
-    let add = separated_pair(
-        tag("ADD"),
+//the mnemonic can be anything in the table or an alias for it, and whether it takes an operand comes from there too
+#[allow(clippy::type_complexity)]
+fn ops<'a>(
+    i: &'a str,
+    options: &ParseOptions,
+) -> IResult<&'a str, (&'a str, Vec<(&'a str, Operand)>), VerboseError<&'a str>> {
+    let (i, mnemonic): (_, &Mnemonic) = map_opt(identifier, |name| options.mnemonic(name))(i)?;
+    if mnemonic.operand {
+        let (i, operand) = preceded(space1, spanned_operand)(i)?;
+        Ok((i, (mnemonic.name, vec![operand])))
+    } else {
+        Ok((i, (mnemonic.name, vec![])))
+    }
+}
+
+//parse assembler directives, which place data rather than instructions
+#[allow(clippy::type_complexity)]
+fn directives(i: &str) -> IResult<&str, (&str, Vec<(&str, Operand)>), VerboseError<&str>> {
+    //raw bytes, as many as you like
+    let bytes = separated_pair(
+        alt((keyword("DB"), keyword(".byte"))),
         space1,
-        flat_map(u8, |o| success(Some(Operand::Number(o)))),
+        separated_list1(delimited(space0, tag(","), space0), spanned_operand),
     );
 
-    let load = separated_pair(
-        tag("LOAD"),
+    //some number of zeroed bytes, which has to be known up front to lay out memory
+    let space = separated_pair(
+        alt((keyword("DS"), keyword(".space"))),
         space1,
-        flat_map(u8, |o| success(Some(Operand::Number(o)))),
+        map(spanned_operand, |o| vec![o]),
     );
 
-    let store = separated_pair(
-        tag("STORE"),
+    //move the current address somewhere else
+    let origin = separated_pair(
+        alt((keyword("ORG"), keyword(".org"))),
         space1,
-        flat_map(u8, |o| success(Some(Operand::Number(o)))),
+        map(spanned_operand, |o| vec![o]),
     );
 
-    let jump = separated_pair(
-        tag("JUMP"),
+    //symbols shared with other modules, and symbols used from them, when the program is linked
+    let names = || {
+        separated_list1(
+            delimited(space0, tag(","), space0),
+            consumed(map(identifier, |name: &str| {
+                Operand::Label(name.to_owned(), 0)
+            })),
+        )
+    };
+    let global = separated_pair(
+        alt((keyword("GLOBAL"), keyword(".global"))),
         space1,
-        flat_map(alphanumeric1, |l: &str| {
-            success(Some(Operand::Label(l.to_owned())))
-        }),
+        names(),
     );
-    let branch = separated_pair(
-        alt((tag("BNZ"), tag("BUZ"), tag("BZC"), tag("BNE"))),
+    let external = separated_pair(
+        alt((keyword("EXTERN"), keyword(".extern"))),
         space1,
-        flat_map(alphanumeric1, |l: &str| {
-            success(Some(Operand::Label(l.to_owned())))
+        names(),
+    );
+
+    alt((bytes, space, origin, global, external))(i)
+}
+
+//constant definitions, either NAME EQU value or .set NAME, value
+#[allow(clippy::type_complexity)]
+fn constant(
+    i: &str,
+) -> IResult<&str, (Option<&str>, (&str, Vec<(&str, Operand)>)), VerboseError<&str>> {
+    let equ = map(
+        tuple((identifier, space1, keyword("EQU"), space1, spanned_operand)),
+        |(name, _, opcode, _, value)| (Some(name), (opcode, vec![value])),
+    );
+    let set = map(
+        tuple((
+            keyword(".set"),
+            space1,
+            identifier,
+            alt((delimited(space0, tag(","), space0), space1)),
+            spanned_operand,
+        )),
+        |(opcode, _, name, _, value)| (Some(name), (opcode, vec![value])),
+    );
+    alt((equ, set))(i)
+}
+
+//an operand along with the text it was parsed from, so errors can point at it
+fn spanned_operand(i: &str) -> IResult<&str, (&str, Operand), VerboseError<&str>> {
+    consumed(operand)(i)
+}
+
+//an operand that can be either a number or a label, optionally plus or minus a number
+fn operand(i: &str) -> IResult<&str, Operand, VerboseError<&str>> {
+    let offset = opt(tuple((delimited(space0, one_of("+-"), space0), number)));
+    let label = pair(identifier, offset);
+
+    alt((
+        map(number, Operand::Number),
+        map(label, |(l, offset)| {
+            let offset = match offset {
+                Some(('-', n)) => -n,
+                Some((_, n)) => n,
+                None => 0,
+            };
+            Operand::Label(l.to_owned(), offset)
         }),
+    ))(i)
+}
+
+//numbers can be decimal, hex (0x1F), binary (0b10101 or %10101), or an ascii character ('A')
+//they're range checked later on, so anything too long to even fit in an i64 just becomes i64::MAX
+fn number(i: &str) -> IResult<&str, i64, VerboseError<&str>> {
+    let parse = |digits: &str, radix| i64::from_str_radix(digits, radix).unwrap_or(i64::MAX);
+
+    let hex = map(preceded(tag_no_case("0x"), hex_digit1), move |d| {
+        parse(d, 16)
+    });
+    let binary = map(
+        preceded(alt((tag_no_case("0b"), tag("%"))), is_a("01")),
+        move |d| parse(d, 2),
     );
+    let decimal = map(digit1, move |d| parse(d, 10));
+    let character = map(delimited(char('\''), none_of("'"), char('\'')), |c| {
+        c as i64
+    });
+
+    alt((hex, binary, decimal, character))(i)
+}
 
-    alt((add, load, store, jump, clear, branch, inc, dec, stop))(i)
+//labels have to start with a letter or underscore so they can't be mistaken for numbers
+fn identifier(i: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(i)
 }
 
 //parse the label off the front of the instruction
 fn label(i: &str) -> IResult<&str, Option<&str>, VerboseError<&str>> {
-    opt(terminated(terminated(alphanumeric1, tag(":")), space0))(i)
+    opt(terminated(terminated(identifier, tag(":")), space0))(i)
 }
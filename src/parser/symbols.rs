@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use super::{Line, Operand, ParseError, ParseOptions, Span};
+
+//the symbol table: labels are addresses, constants are values that can refer to other symbols
+//externals are symbols from other modules, which only get a value when the program is linked
+//all of them are looked up by their key, which is the name as written unless case doesn't matter
+pub(super) struct SymbolTable<'a> {
+    labels: HashMap<String, Label>,
+    constants: HashMap<String, &'a Line>,
+    externals: HashMap<String, (String, Span)>,
+    options: &'a ParseOptions,
+}
+
+//what a value is relative to: nothing, the start of the module (for anything that's an address),
+//or a symbol from another module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Base {
+    Absolute,
+    Relocatable,
+    External(String),
+}
+
+//a label, spelt the way it was first written, and where that was
+struct Label {
+    name: String,
+    address: usize,
+    span: Span,
+}
+
+impl<'a> SymbolTable<'a> {
+    //collect up all the constants and externals, making sure none of them are defined twice (the first definition is kept)
+    //labels can't be added until the program has been laid out
+    pub(super) fn new(
+        lines: &'a [Line],
+        options: &'a ParseOptions,
+        errors: &mut Vec<ParseError>,
+    ) -> SymbolTable<'a> {
+        let mut constants: HashMap<String, &Line> = HashMap::new();
+        for line in lines.iter().filter(|line| line.is_constant()) {
+            if let Some(name) = &line.label {
+                if let Some(first) = constants.get(&options.symbol_key(name)) {
+                    errors.push(line.in_context(ParseError::SymbolRedefined(
+                        name.clone(),
+                        line.span.clone(),
+                        first.span.clone(),
+                    )));
+                    continue;
+                }
+                //reserved names are still defined, so anything using them doesn't get an error too
+                constants.insert(options.symbol_key(name), line);
+                if options.is_reserved(name) {
+                    errors.push(
+                        line.in_context(ParseError::ReservedName(name.clone(), line.span.clone())),
+                    );
+                }
+            }
+        }
+
+        let mut externals: HashMap<String, (String, Span)> = HashMap::new();
+        for line in lines
+            .iter()
+            .filter(|line| matches!(line.opcode.as_str(), "EXTERN" | ".extern"))
+        {
+            for (operand, span) in &line.operands {
+                let name = match operand {
+                    Operand::Label(name, _) => name,
+                    Operand::Number(_) => continue,
+                };
+                let key = options.symbol_key(name);
+                let first =
+                    match (constants.get(&key), externals.get(&key)) {
+                        (Some(constant), _) => &constant.span,
+                        (_, Some((_, first))) => first,
+                        (None, None) => {
+                            externals.insert(key, (name.clone(), span.clone()));
+                            if options.is_reserved(name) {
+                                errors.push(line.in_context(ParseError::ReservedName(
+                                    name.clone(),
+                                    span.clone(),
+                                )));
+                            }
+                            continue;
+                        }
+                    };
+                errors.push(line.in_context(ParseError::SymbolRedefined(
+                    name.clone(),
+                    span.clone(),
+                    first.clone(),
+                )));
+            }
+        }
+
+        SymbolTable {
+            labels: HashMap::new(),
+            constants,
+            externals,
+            options,
+        }
+    }
+
+    //labels can't share a name with another label, a constant or an external either
+    pub(super) fn add_label(
+        &mut self,
+        name: &str,
+        address: usize,
+        line: &Span,
+    ) -> Result<(), ParseError> {
+        let key = self.options.symbol_key(name);
+        let first = match (
+            self.labels.get(&key),
+            self.constants.get(&key),
+            self.externals.get(&key),
+        ) {
+            (Some(label), _, _) => &label.span,
+            (_, Some(constant), _) => &constant.span,
+            (_, _, Some((_, external))) => external,
+            (None, None, None) => {
+                let label = Label {
+                    name: name.to_owned(),
+                    address,
+                    span: line.clone(),
+                };
+                self.labels.insert(key, label);
+                if self.options.is_reserved(name) {
+                    return Err(ParseError::ReservedName(name.to_owned(), line.clone()));
+                }
+                return Ok(());
+            }
+        };
+        Err(ParseError::SymbolRedefined(
+            name.to_owned(),
+            line.clone(),
+            first.clone(),
+        ))
+    }
+
+    //work out the value of an operand, following constants through as many other symbols as it takes
+    //externals don't have a value until the program is linked, so they're an error here
+    pub(super) fn value(&self, operand: &Operand, line: &Span) -> Result<i64, ParseError> {
+        match self.resolve(operand, line)? {
+            (_, Base::External(name)) => Err(ParseError::ExternalSymbol(name, line.clone())),
+            (value, _) => Ok(value),
+        }
+    }
+
+    //the value of an operand, and what it's relative to
+    //for externals, the value is just the offset that gets added to the symbol once it's known
+    pub(super) fn resolve(
+        &self,
+        operand: &Operand,
+        line: &Span,
+    ) -> Result<(i64, Base), ParseError> {
+        self.evaluate(operand, line, &mut Vec::new())
+    }
+
+    fn evaluate(
+        &self,
+        operand: &Operand,
+        line: &Span,
+        visiting: &mut Vec<String>,
+    ) -> Result<(i64, Base), ParseError> {
+        let (name, offset) = match operand {
+            Operand::Number(n) => return Ok((*n, Base::Absolute)),
+            Operand::Label(name, offset) => (name, *offset),
+        };
+        let key = self.options.symbol_key(name);
+
+        let (value, base) = if let Some(label) = self.labels.get(&key) {
+            (label.address as i64, Base::Relocatable)
+        } else if let Some((name, _)) = self.externals.get(&key) {
+            (0, Base::External(name.clone()))
+        } else if let Some(definition) = self.constants.get(&key) {
+            //if we're already in the middle of working this one out then it depends on itself
+            //the error always names the same constant in the loop, wherever it was found from, so it's only reported once
+            if let Some(start) = visiting.iter().position(|v| *v == key) {
+                let first = &self.constants[visiting[start..].iter().min().unwrap_or(&key)];
+                return Err(ParseError::CircularDefinition(
+                    first.label.clone().unwrap_or_default(),
+                    first.span.clone(),
+                ));
+            }
+            visiting.push(key);
+            let (value, span) = &definition.operands[0];
+            let value = self.evaluate(value, span, visiting)?;
+            visiting.pop();
+            value
+        } else {
+            return Err(ParseError::InvalidSymbol(name.clone(), line.clone()));
+        };
+        Ok((value.saturating_add(offset), base))
+    }
+
+    //every symbol and what it resolves to, for the debug info
+    //any constants that can't be worked out are left out, as are any that depend on externals
+    pub(super) fn resolve_all(&self, errors: &mut Vec<ParseError>) -> HashMap<String, usize> {
+        let mut symbols: HashMap<String, usize> = self
+            .labels
+            .values()
+            .map(|label| (label.name.clone(), label.address))
+            .collect();
+        for definition in self.constants.values() {
+            let (value, span) = &definition.operands[0];
+            let value = self.resolve(value, span).and_then(|(value, base)| {
+                let value = value
+                    .try_into()
+                    .map_err(|_| ParseError::OperandOverflow(span.clone()))?;
+                Ok((value, base))
+            });
+            match value {
+                Ok((_, Base::External(_))) => (),
+                Ok((value, _)) => {
+                    symbols.insert(definition.label.clone().unwrap_or_default(), value);
+                }
+                Err(e) => errors.push(definition.in_context(e)),
+            }
+        }
+        symbols
+    }
+}
@@ -3,6 +3,17 @@
 use Instruction::*;
 
 use super::*;
+use crate::object::{Export, Field, Object, Reference, Relocation};
+
+//where errors in programs that aren't from a file are
+fn span(line: usize, column: usize, len: usize) -> Span {
+    Span {
+        file: String::new(),
+        line,
+        column,
+        len,
+    }
+}
 
 //test one of each really simple operands
 #[test]
@@ -42,7 +53,7 @@ fn comments() {
     assert_eq!(parse_file("DEC       ; test comment  "), Ok(vec![Dec]));
     assert_eq!(
         parse_file("ADD  100     ; test comment  "),
-        Err(ParseError::OperandOverflow(0))
+        Err(vec![ParseError::OperandOverflow(span(1, 6, 3))])
     );
     assert_eq!(
         parse_file("   LOAD  21     ; test comment DEC  "),
@@ -51,47 +62,53 @@ fn comments() {
     assert_eq!(parse_file("   LOAD  31     ;; test;  "), Ok(vec![Load(31)]));
 }
 
-// //make sure we get the right errors
-// disabled for now beacuse the error reporting is bad
-// #[test]
-// fn errors() {
-//     assert_eq!(
-//         parse_file("ABC"),
-//         Err(ParseError::BadInput(0, "".to_owned()))
-//     );
-//     assert_eq!(
-//         parse_file("DEC 12"),
-//         Err(ParseError::BadInput(0, "".to_owned()))
-//     );
-//     assert_eq!(
-//         parse_file("DEC 12; INC"),
-//         Err(ParseError::UnexpectedSymbol(0))
-//     );
-//     assert_eq!(
-//         parse_file("STORE 12 INC"),
-//         Err(ParseError::UnexpectedSymbol(0))
-//     );
-//     assert_eq!(
-//         parse_file("ADD x ;"),
-//         Err(ParseError::MissingOperandError(0))
-//     );
-//     assert_eq!(
-//         parse_file("STOP 14 ;"),
-//         Err(ParseError::UnexpectedSymbol(0))
-//     );
-//     assert_eq!(
-//         parse_file("DEEZ NUTS ; haha"),
-//         Err(ParseError::InvalidOpcode(0))
-//     );
-//     assert_eq!(
-//         parse_file(" CLEAR \n ADD x"),
-//         Err(ParseError::MissingOperandError(1))
-//     );
-//     assert_eq!(
-//         parse_file(" CLEAR \n SUB 12"),
-//         Err(ParseError::InvalidOpcode(1))
-//     );
-// }
+//make sure we get the right errors, pointing at the part of the line that's wrong
+#[test]
+fn errors() {
+    assert_eq!(
+        parse_file("ABC"),
+        Err(vec![ParseError::BadInput(span(1, 1, 3))])
+    );
+    assert_eq!(
+        parse_file("DEC 12"),
+        Err(vec![ParseError::BadInput(span(1, 5, 2))])
+    );
+    assert_eq!(
+        parse_file("DEC 12; INC"),
+        Err(vec![ParseError::BadInput(span(1, 5, 2))])
+    );
+    assert_eq!(
+        parse_file("STORE 12 INC"),
+        Err(vec![ParseError::BadInput(span(1, 10, 3))])
+    );
+    //operands can be labels, so this is a symbol that isn't defined anywhere
+    assert_eq!(
+        parse_file("ADD x ;"),
+        Err(vec![ParseError::InvalidSymbol(
+            "x".to_owned(),
+            span(1, 5, 1)
+        )])
+    );
+    assert_eq!(
+        parse_file("STOP 14 ;"),
+        Err(vec![ParseError::BadInput(span(1, 6, 2))])
+    );
+    assert_eq!(
+        parse_file("DEEZ NUTS ; haha"),
+        Err(vec![ParseError::BadInput(span(1, 1, 4))])
+    );
+    assert_eq!(
+        parse_file(" CLEAR \n ADD x"),
+        Err(vec![ParseError::InvalidSymbol(
+            "x".to_owned(),
+            span(2, 6, 1)
+        )])
+    );
+    assert_eq!(
+        parse_file(" CLEAR \n SUB 12"),
+        Err(vec![ParseError::BadInput(span(2, 2, 3))])
+    );
+}
 
 #[test]
 fn file_empty() {
@@ -109,3 +126,900 @@ fn multiple_instructions() {
         Ok(vec![Clear(0), Add(15), Store(0), Clear(1),])
     );
 }
+
+//source map should point at the real line numbers, skipping blanks and comments
+#[test]
+fn source_map() {
+    let (instructions, DebugInfo { source_map, .. }) =
+        parse_file_with_source("; comment\n\nCLEAR\n  loop: INC ; up\nJUMP loop").unwrap();
+    assert_eq!(instructions, vec![Clear(0), Inc, Jump(1)]);
+    assert_eq!(
+        source_map.get(&1),
+        Some(&SourceLine {
+            number: 4,
+            text: "loop: INC ; up".to_owned()
+        })
+    );
+    assert_eq!(source_map.get(&2).map(|l| l.number), Some(5));
+    assert_eq!(source_map.len(), 3);
+}
+
+#[test]
+fn symbol_table() {
+    let (_, debug_info) = parse_file_with_source("CLEAR\nstart: INC\n\nend: JUMP start").unwrap();
+    assert_eq!(debug_info.symbols.get("start"), Some(&1));
+    assert_eq!(debug_info.symbols.get("end"), Some(&2));
+    assert_eq!(debug_info.symbols.len(), 2);
+}
+
+//raw bytes and reserved space, with labels that resolve like any other
+#[test]
+fn data_directives() {
+    assert_eq!(parse_file("DB 15"), Ok(vec![Clear(15)]));
+    assert_eq!(
+        parse_file(".byte 1, 32 ,255"),
+        Ok(vec![Clear(1), Inc, Store(31)])
+    );
+    assert_eq!(parse_file("DS 3"), Ok(vec![Clear(0); 3]));
+    assert_eq!(parse_file(".space 0"), Ok(vec![]));
+
+    let (instructions, debug_info) = parse_file_with_source(
+        "LOAD counter\nDEC\nSTORE counter\nSTOP\ncounter: DB 3\nDS 2\nend: DB counter",
+    )
+    .unwrap();
+    assert_eq!(
+        instructions,
+        vec![
+            Load(4),
+            Dec,
+            Store(4),
+            Clear(1),
+            Clear(3),
+            Clear(0),
+            Clear(0),
+            Clear(4)
+        ]
+    );
+    assert_eq!(debug_info.symbols.get("counter"), Some(&4));
+    assert_eq!(debug_info.symbols.get("end"), Some(&7));
+    //all the reserved bytes point back to the same line
+    assert_eq!(debug_info.source_map.get(&6).map(|l| l.number), Some(6));
+
+    assert_eq!(
+        parse_file("LOAD nowhere"),
+        Err(vec![ParseError::InvalidSymbol(
+            "nowhere".to_owned(),
+            span(1, 6, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("DS 20\nDS 13"),
+        Err(vec![ParseError::ProgramTooLong(33)])
+    );
+    assert!(matches!(
+        parse_file("DB").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+    //the size has to be known up front, so it can't depend on a label
+    assert_eq!(
+        parse_file("x: DS x"),
+        Err(vec![ParseError::InvalidSymbol(
+            "x".to_owned(),
+            span(1, 7, 1)
+        )])
+    );
+}
+
+//placing things at specific addresses
+#[test]
+fn origin() {
+    let (instructions, debug_info) =
+        parse_file_with_source("ORG 30\ndata: DB 1, 2\n.org 0\nLOAD data\nSTOP").unwrap();
+    let mut expected = vec![Clear(0); 32];
+    expected[..2].copy_from_slice(&[Load(30), Clear(1)]);
+    expected[30..].copy_from_slice(&[Clear(1), Clear(2)]);
+    assert_eq!(instructions, expected);
+    assert_eq!(debug_info.symbols.get("data"), Some(&30));
+    //the gap isn't from any line
+    assert_eq!(debug_info.source_map.get(&2), None);
+    assert_eq!(debug_info.source_map.get(&30).map(|l| l.number), Some(2));
+
+    //labels on an ORG point at the new address
+    assert_eq!(
+        parse_file("here: ORG 2\nJUMP here"),
+        Ok(vec![Clear(0), Clear(0), Jump(2)])
+    );
+    //nothing is placed after a trailing ORG, so it doesn't make the program any longer
+    assert_eq!(parse_file("ORG 32"), Ok(vec![]));
+    assert_eq!(parse_file("STOP\nORG 10"), Ok(vec![Clear(1)]));
+    //or a module, which would leave less room for the others when linking
+    let (object, _) = parse_object("STOP\nORG 10", "", &ParseOptions::default()).unwrap();
+    assert_eq!(object.code, [1]);
+
+    assert_eq!(
+        parse_file("CLEAR\nINC\nORG 1\nDEC"),
+        Err(vec![ParseError::OverlappingAddress(
+            1,
+            span(4, 1, 3),
+            span(2, 1, 3)
+        )])
+    );
+    assert_eq!(
+        parse_file("ORG 31\nDB 1, 2"),
+        Err(vec![ParseError::ProgramTooLong(33)])
+    );
+    assert_eq!(
+        parse_file("ORG 33"),
+        Err(vec![ParseError::OperandOverflow(span(1, 5, 2))])
+    );
+}
+
+//any operand can be a number, a label, or a label plus or minus something
+#[test]
+fn symbolic_operands() {
+    assert_eq!(parse_file("JUMP 3"), Ok(vec![Jump(3)]));
+    assert_eq!(parse_file("BNZ 31"), Ok(vec![Bnz(31)]));
+    assert_eq!(
+        parse_file("x: ADD x+5\nADD x - 0\nLOAD y-1\nSTORE y+1\ny: JUMP y  ; end"),
+        Ok(vec![Add(5), Add(0), Load(3), Store(5), Jump(4)])
+    );
+    assert_eq!(
+        parse_file("table: DB table+2, end, 255\nend: BNZ table+1"),
+        Ok(vec![Clear(2), Clear(3), Store(31), Bnz(1)])
+    );
+
+    //out of range either way
+    assert_eq!(
+        parse_file("CLEAR\nx: LOAD x-2"),
+        Err(vec![ParseError::OperandOverflow(span(2, 9, 3))])
+    );
+    assert_eq!(
+        parse_file("x: JUMP x+32"),
+        Err(vec![ParseError::OperandOverflow(span(1, 9, 4))])
+    );
+    assert_eq!(
+        parse_file("DB 0\nx: DB x+255"),
+        Err(vec![ParseError::OperandOverflow(span(2, 7, 5))])
+    );
+    //anything left over after the operand is an error, not ignored
+    assert!(matches!(
+        parse_file("x: JUMP x+").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+    assert_eq!(
+        parse_file("ADD y+1"),
+        Err(vec![ParseError::InvalidSymbol(
+            "y".to_owned(),
+            span(1, 5, 3)
+        )])
+    );
+}
+
+//operands can be written in whatever base is convenient
+#[test]
+fn literals() {
+    assert_eq!(parse_file("ADD 0x1F"), Ok(vec![Add(31)]));
+    assert_eq!(parse_file("ADD 0X0a"), Ok(vec![Add(10)]));
+    assert_eq!(parse_file("LOAD 0b10101"), Ok(vec![Load(21)]));
+    assert_eq!(parse_file("STORE %00011"), Ok(vec![Store(3)]));
+    assert_eq!(parse_file("DB 'A', ' ', ';'"), Ok(vec![Add(1), Inc, Inc]));
+    assert_eq!(
+        parse_file("DS 0x2\nx: JUMP x+0b1"),
+        Ok(vec![Clear(0), Clear(0), Jump(3)])
+    );
+
+    //out of range literals are caught on the right line, whatever base they're in
+    assert_eq!(
+        parse_file("CLEAR\nADD 0x20"),
+        Err(vec![ParseError::OperandOverflow(span(2, 5, 4))])
+    );
+    assert_eq!(
+        parse_file("DB 256"),
+        Err(vec![ParseError::OperandOverflow(span(1, 4, 3))])
+    );
+    assert_eq!(
+        parse_file("DB 0x100"),
+        Err(vec![ParseError::OperandOverflow(span(1, 4, 5))])
+    );
+    assert_eq!(
+        parse_file("INC\nINC\nLOAD %100000"),
+        Err(vec![ParseError::OperandOverflow(span(3, 6, 7))])
+    );
+    assert_eq!(
+        parse_file("ADD 'A'"),
+        Err(vec![ParseError::OperandOverflow(span(1, 5, 3))])
+    );
+    assert_eq!(
+        parse_file("DB 99999999999999999999999"),
+        Err(vec![ParseError::OperandOverflow(span(1, 4, 23))])
+    );
+    assert_eq!(
+        parse_file("DS 0x21"),
+        Err(vec![ParseError::OperandOverflow(span(1, 4, 4))])
+    );
+
+    //not actually numbers
+    assert!(matches!(
+        parse_file("ADD 0b102").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+    assert!(matches!(
+        parse_file("ADD 0xG").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+    assert!(matches!(
+        parse_file("DB ''").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+}
+
+//named constants, which can be used anywhere an operand can
+#[test]
+fn constants() {
+    let (instructions, debug_info) = parse_file_with_source(
+        "COUNTER EQU 31\nSTART EQU 4\n.set SIZE, 2\n.set NEXT COUNTER-1\nORG START\nLOAD COUNTER\nSTORE NEXT\nloop: DS SIZE\nJUMP loop+2",
+    )
+    .unwrap();
+    assert_eq!(
+        instructions,
+        vec![
+            Clear(0),
+            Clear(0),
+            Clear(0),
+            Clear(0),
+            Load(31),
+            Store(30),
+            Clear(0),
+            Clear(0),
+            Jump(8)
+        ]
+    );
+    assert_eq!(debug_info.symbols.get("COUNTER"), Some(&31));
+    assert_eq!(debug_info.symbols.get("NEXT"), Some(&30));
+    assert_eq!(debug_info.symbols.get("loop"), Some(&6));
+    //constants don't take up any space
+    assert_eq!(debug_info.source_map.get(&4).map(|l| l.number), Some(6));
+
+    //constants can refer to labels, and be defined after they're used
+    assert_eq!(
+        parse_file("LOAD VALUE\nSTOP\nx: DB 5\nVALUE EQU x"),
+        Ok(vec![Load(2), Clear(1), Clear(5)])
+    );
+    //they only have to fit where they're used
+    assert_eq!(parse_file("BIG EQU 300\nADD BIG-290"), Ok(vec![Add(10)]));
+    assert_eq!(
+        parse_file("BIG EQU 300\nADD BIG"),
+        Err(vec![ParseError::OperandOverflow(span(2, 5, 3))])
+    );
+
+    assert_eq!(
+        parse_file("X EQU 1\nX EQU 2"),
+        Err(vec![ParseError::SymbolRedefined(
+            "X".to_owned(),
+            span(2, 1, 7),
+            span(1, 1, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("X EQU 1\nX: INC"),
+        Err(vec![ParseError::SymbolRedefined(
+            "X".to_owned(),
+            span(2, 1, 6),
+            span(1, 1, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("A EQU B\nB EQU C+1\nC EQU A\nADD A"),
+        Err(vec![ParseError::CircularDefinition(
+            "A".to_owned(),
+            span(1, 1, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("A EQU A"),
+        Err(vec![ParseError::CircularDefinition(
+            "A".to_owned(),
+            span(1, 1, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("A EQU B"),
+        Err(vec![ParseError::InvalidSymbol(
+            "B".to_owned(),
+            span(1, 7, 1)
+        )])
+    );
+}
+
+//macros are expanded in place, with their own copy of any labels inside them
+#[test]
+fn macros() {
+    let (instructions, debug_info) = parse_file_with_source(
+        "MACRO countdown from, to\nLOAD from\nloop: DEC\nBNZ loop\nSTORE to\nENDM\n\nstart: countdown 30, 31\ncountdown x+1, 30 ; again\nSTOP\nx: DB 3, 4",
+    )
+    .unwrap();
+    assert_eq!(
+        instructions,
+        vec![
+            Load(30),
+            Dec,
+            Bnz(1),
+            Store(31),
+            Load(10),
+            Dec,
+            Bnz(5),
+            Store(30),
+            Clear(1),
+            Clear(3),
+            Clear(4)
+        ]
+    );
+    assert_eq!(debug_info.symbols.get("start"), Some(&0));
+    assert_eq!(debug_info.symbols.get("loop@2"), Some(&5));
+    //the expansion maps back to where the macro was used
+    assert_eq!(debug_info.source_map.get(&5).map(|l| l.number), Some(9));
+
+    //no parameters at all is fine too
+    assert_eq!(
+        parse_file("MACRO twice\nINC\nINC\nENDM\ntwice\ntwice"),
+        Ok(vec![Inc, Inc, Inc, Inc])
+    );
+
+    //errors inside the body say where it was used as well
+    assert_eq!(
+        parse_file("MACRO put a\nSTORE a\nENDM\nput 30\nput 40"),
+        Err(vec![ParseError::InMacro(
+            "put".to_owned(),
+            span(5, 1, 6),
+            Box::new(ParseError::OperandOverflow(span(2, 7, 1)))
+        )])
+    );
+    assert_eq!(
+        parse_file("MACRO put a\nSTORE a\nENDM\nput 30, 31"),
+        Err(vec![ParseError::MacroArguments(
+            "put".to_owned(),
+            1,
+            2,
+            span(4, 1, 10)
+        )])
+    );
+    assert_eq!(
+        parse_file("MACRO put a\nSTORE a\nINC"),
+        Err(vec![ParseError::UnterminatedMacro(
+            "put".to_owned(),
+            span(1, 1, 11)
+        )])
+    );
+    assert_eq!(
+        parse_file("MACRO m\nENDM\nMACRO m\nENDM"),
+        Err(vec![ParseError::SymbolRedefined(
+            "m".to_owned(),
+            span(3, 1, 7),
+            span(1, 1, 7)
+        )])
+    );
+    //macros have to be defined before they're used
+    assert!(matches!(
+        parse_file("twice\nMACRO twice\nINC\nINC\nENDM").unwrap_err()[..],
+        [ParseError::BadInput(Span { line: 1, .. })]
+    ));
+}
+
+//included files are read relative to the file including them, and errors in them say which file they're in
+#[test]
+fn includes() {
+    let dir = std::env::temp_dir().join(format!("patp-include-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    let write = |name: &str, contents: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    };
+
+    write("lib/constants.patp", "COUNTER EQU 31\n");
+    write(
+        "lib/countdown.patp",
+        "INCLUDE \"constants.patp\"\nMACRO countdown\nloop: LOAD COUNTER\nDEC\nSTORE COUNTER\nBNZ loop\nENDM\n",
+    );
+    let main = "INCLUDE \"lib/countdown.patp\" ; shared routines\n\nstart: countdown\nSTOP";
+    let path = write("main.patp", main);
+    let (instructions, debug_info) = parse_file_from(main, &path).unwrap();
+    assert_eq!(
+        instructions,
+        vec![Load(31), Dec, Store(31), Bnz(0), Clear(1)]
+    );
+    assert_eq!(debug_info.symbols.get("COUNTER"), Some(&31));
+    assert_eq!(debug_info.source_map.get(&4).map(|l| l.number), Some(4));
+
+    //errors in included files point at that file
+    write("lib/bad.patp", "INC\nADD 40");
+    let main = "INCLUDE \"lib/bad.patp\"";
+    assert_eq!(
+        parse_file_from(main, write("bad.patp", main)),
+        Err(vec![ParseError::OperandOverflow(Span {
+            file: dir.join("lib/bad.patp").display().to_string(),
+            line: 2,
+            column: 5,
+            len: 2
+        })])
+    );
+
+    assert!(matches!(
+        parse_file_from("INCLUDE \"missing.patp\"", &path).unwrap_err()[..],
+        [ParseError::IncludeError(..)]
+    ));
+
+    //files can't include themselves, even through something else
+    let first = write("first.patp", "INCLUDE \"second.patp\"");
+    write("second.patp", "INC\nINCLUDE \"first.patp\"");
+    assert_eq!(
+        parse_file_from("INCLUDE \"second.patp\"", &first),
+        Err(vec![ParseError::CircularInclude(
+            Span {
+                file: dir.join("second.patp").display().to_string(),
+                line: 2,
+                column: 1,
+                len: 20
+            },
+            dir.join("first.patp").display().to_string()
+        )])
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+//errors point at exactly what's wrong, and render with the line they're on
+#[test]
+fn diagnostics() {
+    //real line numbers, blank lines and comments included
+    assert_eq!(
+        parse_file("; a comment\n\nCLEAR\n  ADD 40"),
+        Err(vec![ParseError::OperandOverflow(span(4, 7, 2))])
+    );
+    //syntax errors point at wherever the parser got stuck
+    assert_eq!(
+        parse_file("x: JUMP x+"),
+        Err(vec![ParseError::BadInput(span(1, 10, 1))])
+    );
+    assert_eq!(
+        parse_file("DB"),
+        Err(vec![ParseError::BadInput(span(1, 1, 2))])
+    );
+    assert_eq!(
+        parse_file("LOAD 3\nDEEZ NUTS"),
+        Err(vec![ParseError::BadInput(span(2, 1, 4))])
+    );
+
+    let source = "start: LOAD start\n\tADD 40 ; too big";
+    assert_eq!(
+        parse_file(source).unwrap_err()[0].render(source, ""),
+        "Invalid operand: operand is out of range
+ --> 2:6
+  |
+2 | \tADD 40 ; too big
+  | \t    ^^
+"
+    );
+
+    //columns count bytes, but the carets still go under the right characters
+    let source = "DB 'é', 300";
+    assert_eq!(
+        parse_file(source).unwrap_err()[0].render(source, ""),
+        "Invalid operand: operand is out of range
+ --> 1:10
+  |
+1 | DB 'é', 300
+  |         ^^^
+"
+    );
+
+    let source = "MACRO put a\n  STORE a\nENDM\nput 40";
+    assert_eq!(
+        parse_file(source).unwrap_err()[0].render(source, ""),
+        "Invalid operand: operand is out of range
+ --> 2:9
+  |
+2 |   STORE a
+  |         ^
+note: in macro put, used here
+ --> 4:1
+  |
+4 | put 40
+  | ^^^^^^
+"
+    );
+
+    assert_eq!(
+        ParseError::ProgramTooLong(33).render("", ""),
+        "Program is too long: contains 33 instructions (max 32)\n"
+    );
+}
+
+//everything that's wrong is reported at once, in the order it appears
+#[test]
+fn multiple_errors() {
+    assert_eq!(
+        parse_file("ADD 40\nx: INC\nx EQU 2\nLOAD\nJUMP nowhere\nSTORE 3"),
+        Err(vec![
+            ParseError::OperandOverflow(span(1, 5, 2)),
+            ParseError::SymbolRedefined("x".to_owned(), span(2, 1, 6), span(3, 1, 7)),
+            ParseError::BadInput(span(4, 5, 1)),
+        ])
+    );
+    //once a line can't be parsed, it might have been what defined an unknown symbol
+    assert_eq!(
+        parse_file("ADD 40\nJUMP nowhere\nSTORE 3"),
+        Err(vec![
+            ParseError::OperandOverflow(span(1, 5, 2)),
+            ParseError::InvalidSymbol("nowhere".to_owned(), span(2, 6, 7)),
+        ])
+    );
+
+    let source = "ADD 40\nDEEZ NUTS";
+    assert_eq!(
+        render_errors(&parse_file(source).unwrap_err(), source, ""),
+        "Invalid operand: operand is out of range
+ --> 1:5
+  |
+1 | ADD 40
+  |     ^^
+
+error: Unrecognised input
+ --> 2:1
+  |
+2 | DEEZ NUTS
+  | ^^^^
+
+error: aborting due to 2 previous errors"
+    );
+}
+
+//suspicious but valid code gets warnings, which can be turned off a line at a time
+#[test]
+fn warnings() {
+    let warnings = |program| parse_file_with_source(program).unwrap().1.warnings;
+
+    assert_eq!(warnings("CLEAR\nloop: INC\nBNZ loop\nSTOP"), vec![]);
+    assert_eq!(
+        warnings("JUMP end\nINC\nDEC\nend: STOP\nINC"),
+        vec![
+            Warning::Unreachable(span(2, 1, 3)),
+            Warning::Unreachable(span(5, 1, 3))
+        ]
+    );
+    assert_eq!(
+        warnings("CLEAR\nunused: STOP\nx: DB 5"),
+        vec![
+            Warning::UnusedLabel("unused".to_owned(), span(2, 1, 12)),
+            Warning::UnusedLabel("x".to_owned(), span(3, 1, 7))
+        ]
+    );
+    assert_eq!(
+        warnings("loop: LOAD x\nBNZ loop\nSTOP\nx: DB 1"),
+        vec![Warning::BranchAfterLoad(span(2, 1, 8))]
+    );
+    //storing into data is fine, storing over code isn't
+    assert_eq!(
+        warnings("start: STORE x\nSTORE start\nSTOP\nx: DB 0"),
+        vec![Warning::SelfModifying(0, span(2, 1, 11))]
+    );
+    //past the end of the program, into gaps left by ORG, and into DS are all just zeros
+    assert_eq!(warnings("INC"), vec![Warning::FallsOffEnd(span(1, 1, 3))]);
+    assert_eq!(
+        warnings("JUMP 10\nSTOP"),
+        vec![
+            Warning::FallsOffEnd(span(1, 1, 7)),
+            Warning::Unreachable(span(2, 1, 4))
+        ]
+    );
+    assert_eq!(
+        warnings("INC\nDS 2\nSTOP"),
+        vec![
+            Warning::FallsOffEnd(span(1, 1, 3)),
+            Warning::Unreachable(span(3, 1, 4))
+        ]
+    );
+
+    //allow() in a comment turns warnings off for just that line
+    assert_eq!(
+        warnings("x: INC ; allow(unused_label, falls_off_end)\ny: STOP ;allow(unreachable)"),
+        vec![Warning::UnusedLabel("y".to_owned(), span(2, 1, 7))]
+    );
+    //including everything that comes out of a macro
+    assert_eq!(
+        warnings("MACRO forever\nloop: JUMP loop\nINC\nENDM\nforever ; allow(unreachable)"),
+        vec![]
+    );
+
+    let source = "INC";
+    assert_eq!(
+        warnings(source)[0].render(source, ""),
+        "Execution can run off the end of the program into zeroed memory, which is all CLEAR
+ --> 1:1
+  |
+1 | INC
+  | ^^^
+  = note: silence this with ; allow(falls_off_end) on the line
+"
+    );
+}
+
+//every symbol has one definition, and can't take the name of something that already means something
+#[test]
+fn symbol_names() {
+    assert_eq!(
+        parse_file("loop: INC\nloop: DEC\nJUMP loop"),
+        Err(vec![ParseError::SymbolRedefined(
+            "loop".to_owned(),
+            span(2, 1, 9),
+            span(1, 1, 9)
+        )])
+    );
+    assert_eq!(
+        parse_file("INC: INC\nJUMP INC"),
+        Err(vec![ParseError::ReservedName(
+            "INC".to_owned(),
+            span(1, 1, 8)
+        )])
+    );
+    assert_eq!(
+        parse_file("STOP EQU 1"),
+        Err(vec![ParseError::ReservedName(
+            "STOP".to_owned(),
+            span(1, 1, 10)
+        )])
+    );
+    assert_eq!(
+        parse_file("MACRO LOAD\nENDM"),
+        Err(vec![ParseError::ReservedName(
+            "LOAD".to_owned(),
+            span(1, 1, 10)
+        )])
+    );
+    //case matters unless asked otherwise, so these are different
+    assert_eq!(
+        parse_file("loop: INC\nLoop: INC\nBNZ loop\nBNZ Loop"),
+        Ok(vec![Inc, Inc, Bnz(0), Bnz(1)])
+    );
+    //but mnemonics never care, so they can't be used in any case
+    assert_eq!(
+        parse_file("inc: INC"),
+        Err(vec![ParseError::ReservedName(
+            "inc".to_owned(),
+            span(1, 1, 8)
+        )])
+    );
+
+    let parse = |program| {
+        parse_file_with_options(
+            program,
+            "",
+            &ParseOptions {
+                case_insensitive: true,
+                ..Default::default()
+            },
+        )
+        .map(|(instructions, _)| instructions)
+    };
+    assert_eq!(
+        parse("Loop: inc\nbnz LOOP\nStop"),
+        Ok(vec![Inc, Bnz(0), Clear(1)])
+    );
+    assert_eq!(
+        parse("x equ 3\n.SET y, X+1\nadd Y\ndb x"),
+        Ok(vec![Add(4), Clear(3)])
+    );
+    assert_eq!(
+        parse("MACRO twice v\nagain: add V\nBNZ Again\nENDM\nTWICE 1\nstop"),
+        Ok(vec![Add(1), Bnz(0), Clear(1)])
+    );
+    assert_eq!(
+        parse("loop: INC\nLOOP: DEC"),
+        Err(vec![ParseError::SymbolRedefined(
+            "LOOP".to_owned(),
+            span(2, 1, 9),
+            span(1, 1, 9)
+        )])
+    );
+    assert_eq!(
+        parse("inc: INC"),
+        Err(vec![ParseError::ReservedName(
+            "inc".to_owned(),
+            span(1, 1, 8)
+        )])
+    );
+    //symbols keep the spelling they were defined with
+    let (_, debug_info) = parse_file_with_options(
+        "Start: JUMP start",
+        "",
+        &ParseOptions {
+            case_insensitive: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(debug_info.symbols["Start"], 0);
+}
+
+//mnemonics come from one table, can be written in any case, and can be given extra names
+#[test]
+fn mnemonics() {
+    assert_eq!(
+        parse_file("JMP 3\nJUMP 3\nBNZ 1\nBUZ 1\nBZC 1\nBNE 1"),
+        Ok(vec![Jump(3), Jump(3), Bnz(1), Bnz(1), Bnz(1), Bnz(1)])
+    );
+    assert_eq!(
+        parse_file("clear\nInc\nadd 2\nloop: dEc\nbnz loop\nx: db 7\nstop"),
+        Ok(vec![Clear(0), Inc, Add(2), Dec, Bnz(3), Clear(7), Clear(1)])
+    );
+    assert_eq!(
+        parse_file("X equ 3\n.SET y, X\norg 2\nds y"),
+        Ok(vec![Clear(0); 5])
+    );
+    assert_eq!(
+        parse_file("INCX"),
+        Err(vec![ParseError::BadInput(span(1, 1, 4))])
+    );
+    assert_eq!(
+        parse_file("JMP"),
+        Err(vec![ParseError::BadInput(span(1, 4, 1))])
+    );
+
+    let mut options = ParseOptions::default();
+    options
+        .add_aliases(
+            "; our names\nHALT = STOP\n  bra=JUMP ; branch always\n\n",
+            "course.cfg",
+        )
+        .unwrap();
+    assert_eq!(
+        parse_file_with_options("start: INC\nBRA start\nhalt", "", &options)
+            .map(|(instructions, _)| instructions),
+        Ok(vec![Inc, Jump(0), Clear(1)])
+    );
+    assert_eq!(
+        parse_file_with_options("halt: STOP", "", &options).map(|(instructions, _)| instructions),
+        Err(vec![ParseError::ReservedName(
+            "halt".to_owned(),
+            span(1, 1, 10)
+        )])
+    );
+
+    let alias = |line: usize, column: usize, len: usize| Span {
+        file: "course.cfg".to_owned(),
+        ..span(line, column, len)
+    };
+    assert_eq!(
+        options.add_aliases(
+            "BRA = JMP\nINC = ADD\nDB = LOAD\nGO = GOTO\nGO STOP",
+            "course.cfg"
+        ),
+        Err(vec![
+            ParseError::SymbolRedefined("BRA".to_owned(), alias(1, 1, 9), alias(3, 3, 8)),
+            ParseError::ReservedName("INC".to_owned(), alias(2, 1, 9)),
+            ParseError::ReservedName("DB".to_owned(), alias(3, 1, 9)),
+            ParseError::InvalidOpcode(alias(4, 6, 4)),
+            ParseError::BadInput(alias(5, 1, 7)),
+        ])
+    );
+}
+
+//modules can be assembled on their own into objects, using each other's symbols, and then linked
+#[test]
+fn objects() {
+    let (main, debug_info) = parse_object(
+        "EXTERN double\nGLOBAL back\nLOAD value\nJUMP double\nback: STOP\nvalue: DB 5",
+        "",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        main,
+        Object {
+            code: vec![0b1100_0011, 0b1000_0000, 1, 5],
+            exports: vec![Export {
+                name: "back".to_owned(),
+                value: 2,
+                relocatable: true,
+            }],
+            references: vec![Reference {
+                offset: 1,
+                field: Field::Operand,
+                symbol: "double".to_owned(),
+            }],
+            relocations: vec![Relocation {
+                offset: 0,
+                field: Field::Operand,
+            }],
+        }
+    );
+    assert_eq!(debug_info.warnings, vec![]);
+
+    //nothing jumps to double here, but other modules can, and jumping to an external leaves the module
+    let (library, debug_info) = parse_object(
+        ".global double, TWO\n.extern back\nTWO EQU 2\ndouble: ADD TWO\nJUMP back\nDB double+1, back",
+        "",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        library,
+        Object {
+            code: vec![0b0100_0010, 0b1000_0000, 1, 0],
+            exports: vec![
+                Export {
+                    name: "double".to_owned(),
+                    value: 0,
+                    relocatable: true,
+                },
+                Export {
+                    name: "TWO".to_owned(),
+                    value: 2,
+                    relocatable: false,
+                },
+            ],
+            references: vec![
+                Reference {
+                    offset: 1,
+                    field: Field::Operand,
+                    symbol: "back".to_owned(),
+                },
+                Reference {
+                    offset: 3,
+                    field: Field::Byte,
+                    symbol: "back".to_owned(),
+                },
+            ],
+            relocations: vec![Relocation {
+                offset: 2,
+                field: Field::Byte,
+            }],
+        }
+    );
+    assert_eq!(debug_info.warnings, vec![]);
+
+    assert_eq!(
+        crate::object::link(&[("main".to_owned(), main), ("library".to_owned(), library)]),
+        Ok(vec![
+            0b1100_0011,
+            0b1000_0100,
+            1,
+            5,
+            0b0100_0010,
+            0b1000_0010,
+            5,
+            2
+        ])
+    );
+
+    //externals only have a value once they're linked
+    assert_eq!(
+        parse_file("EXTERN x\nJUMP x"),
+        Err(vec![ParseError::ExternalSymbol(
+            "x".to_owned(),
+            span(2, 6, 1)
+        )])
+    );
+    assert_eq!(
+        parse_object("EXTERN x\nGLOBAL x", "", &ParseOptions::default()),
+        Err(vec![ParseError::ExternalSymbol(
+            "x".to_owned(),
+            span(2, 8, 1)
+        )])
+    );
+    assert_eq!(
+        parse_file("GLOBAL nowhere\nSTOP"),
+        Err(vec![ParseError::InvalidSymbol(
+            "nowhere".to_owned(),
+            span(1, 8, 7)
+        )])
+    );
+    assert_eq!(
+        parse_file("extern x\nx: STOP"),
+        Err(vec![ParseError::SymbolRedefined(
+            "x".to_owned(),
+            span(2, 1, 7),
+            span(1, 8, 1)
+        )])
+    );
+}
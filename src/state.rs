@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::cpu::Cpu;
+use crate::machine::Run;
+
+//the formats a cpu state can be saved in, for anything that wants to read it other than a person
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateFormat {
+    Json,
+    Toml,
+}
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("Invalid JSON state: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid TOML state: {0}")]
+    Toml(#[from] toml::de::Error),
+}
+
+impl StateFormat {
+    //writes out a run: why it stopped, how many cycles it took, and the state it stopped in
+    pub fn encode(&self, run: &Run) -> String {
+        //a run is only numbers, bools and names, so there's nothing that can fail to serialize
+        match self {
+            StateFormat::Json => serde_json::to_string_pretty(run).expect("run is valid JSON"),
+            StateFormat::Toml => toml::to_string(run).expect("run is valid TOML"),
+        }
+    }
+
+    //reads a state back in, either from a whole run as encode writes it or just the cpu on its own
+    pub fn decode(&self, contents: &str) -> Result<Cpu, StateError> {
+        match self {
+            StateFormat::Json => {
+                let mut value: serde_json::Value = serde_json::from_str(contents)?;
+                let cpu = value.get_mut("cpu").map(serde_json::Value::take);
+                Ok(serde_json::from_value(cpu.unwrap_or(value))?)
+            }
+            StateFormat::Toml => {
+                let mut table: toml::Table = toml::from_str(contents)?;
+                Ok(match table.remove("cpu") {
+                    Some(cpu) => cpu.try_into()?,
+                    None => toml::Value::Table(table).try_into()?,
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for StateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(StateFormat::Json),
+            "toml" => Ok(StateFormat::Toml),
+            _ => Err(format!(
+                "Unknown state format '{}', expected one of json, toml",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instruction::Instruction;
+    use crate::machine::{Machine, RunOutcome};
+    use crate::trace::TraceEntry;
+
+    fn state() -> Cpu {
+        let mut memory = [0; 32];
+        memory[..3].copy_from_slice(&[0b0110_0000, 0b1010_0000, 1]);
+        Cpu::new()
+            .with_memory(memory)
+            .with_register(3)
+            .with_z(true)
+            .with_pc(1)
+    }
+
+    #[test]
+    fn round_trip() {
+        //one that halts, one that runs out of cycles straight away, and one that loops forever
+        let runs = [
+            Machine::from_cpu(state()).run(),
+            Machine::from_cpu(state()).max_cycles(0).run(),
+            Machine::new(&[0b1000_0000]).unwrap().run(),
+        ];
+        for format in [StateFormat::Json, StateFormat::Toml] {
+            for run in &runs {
+                assert_eq!(format.decode(&format.encode(run)).unwrap(), run.cpu);
+            }
+        }
+        let json = StateFormat::Json.encode(&runs[1]);
+        assert!(json.contains("\"outcome\": \"StepLimit\""));
+        assert!(json.contains("\"register\": 3"));
+        let toml = StateFormat::Toml.encode(&runs[2]);
+        assert!(toml.contains("LoopDetected = 0"));
+        assert!(toml.contains("pc = 0"));
+
+        //a cpu saved on its own can be carried on from too
+        let json = serde_json::to_string(&state()).unwrap();
+        assert_eq!(StateFormat::Json.decode(&json).unwrap(), state());
+        let toml = toml::to_string(&state()).unwrap();
+        assert_eq!(StateFormat::Toml.decode(&toml).unwrap(), state());
+    }
+
+    #[test]
+    fn invalid() {
+        let json = serde_json::to_string(&state())
+            .unwrap()
+            .replace("\"pc\":1", "\"pc\":32");
+        let error = StateFormat::Json.decode(&json).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("program counter 32 is outside of memory"));
+
+        assert!(matches!(
+            StateFormat::Toml.decode("pc = 0"),
+            Err(StateError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn runs() {
+        let mut trace = Vec::new();
+        let run = Machine::from_cpu(state()).run_traced(|entry| trace.push(entry));
+        let json = serde_json::to_string(&run).unwrap();
+        assert_eq!(serde_json::from_str::<Run>(&json).unwrap(), run);
+        assert_eq!(run.outcome, RunOutcome::Halted);
+
+        let entry: TraceEntry =
+            serde_json::from_str(&serde_json::to_string(&trace[0]).unwrap()).unwrap();
+        assert_eq!(entry, trace[0]);
+        assert_eq!(entry.instruction, Instruction::Bnz(0));
+    }
+}
@@ -0,0 +1,259 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cpu::Cpu;
+use crate::instruction::Instruction;
+
+//a record of a single executed instruction
+//serialized the same way as the json trace format, so either can be read back in as the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    pub cycle: usize,
+    pub pc: u8,
+    #[cfg_attr(feature = "serde", serde(with = "serde_text"))]
+    pub instruction: Instruction,
+    pub register_before: u8,
+    pub register_after: u8,
+    pub z: bool,
+    //address and value of any memory write
+    #[cfg_attr(feature = "serde", serde(with = "serde_write"))]
+    pub write: Option<(u8, u8)>,
+}
+
+//the formats a trace can be written out in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl TraceEntry {
+    //builds an entry from the cpu state either side of executing an instruction
+    pub fn new(cycle: usize, before: &Cpu, after: &Cpu) -> TraceEntry {
+        let pc = before.pc();
+        let instruction = Instruction::disassemble(before.memory()[pc as usize]);
+        let write = match instruction {
+            Instruction::Store(address) => Some((address, after.memory()[address as usize])),
+            _ => None,
+        };
+        TraceEntry {
+            cycle,
+            pc,
+            instruction,
+            register_before: before.register(),
+            register_after: after.register(),
+            z: after.z(),
+            write,
+        }
+    }
+
+    pub fn format(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Text => self.to_string(),
+            TraceFormat::Csv => {
+                let (address, value) = match self.write {
+                    Some((address, value)) => (address.to_string(), value.to_string()),
+                    None => (String::new(), String::new()),
+                };
+                format!(
+                    "{},{},{},{},{},{},{},{}",
+                    self.cycle,
+                    self.pc,
+                    self.instruction,
+                    self.register_before,
+                    self.register_after,
+                    self.z as i32,
+                    address,
+                    value
+                )
+            }
+            TraceFormat::Json => {
+                let write = match self.write {
+                    Some((address, value)) => {
+                        format!("{{\"address\":{},\"value\":{}}}", address, value)
+                    }
+                    None => "null".to_owned(),
+                };
+                format!(
+                    "{{\"cycle\":{},\"pc\":{},\"instruction\":\"{}\",\"register_before\":{},\"register_after\":{},\"z\":{},\"write\":{}}}",
+                    self.cycle,
+                    self.pc,
+                    self.instruction,
+                    self.register_before,
+                    self.register_after,
+                    self.z,
+                    write
+                )
+            }
+        }
+    }
+}
+
+//the human readable version
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5} | {:>2}: {:<9} | A: {:>3} -> {:>3} | Z: {}",
+            self.cycle,
+            self.pc,
+            self.instruction.to_string(),
+            self.register_before,
+            self.register_after,
+            self.z as i32
+        )?;
+        if let Some((address, value)) = self.write {
+            write!(f, " | [{}] <- {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl TraceFormat {
+    //a line to print before the trace starts, if the format needs one
+    pub fn header(&self) -> Option<&'static str> {
+        match self {
+            TraceFormat::Csv => Some(
+                "cycle,pc,instruction,register_before,register_after,z,write_address,write_value",
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "csv" => Ok(TraceFormat::Csv),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(format!(
+                "Unknown trace format '{}', expected one of text, csv, json",
+                s
+            )),
+        }
+    }
+}
+
+//instructions in traces are written as they'd be in assembly
+#[cfg(feature = "serde")]
+mod serde_text {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::instruction::Instruction;
+
+    pub fn serialize<S: Serializer>(instruction: &Instruction, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(instruction)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Instruction, D::Error> {
+        String::deserialize(d)?.parse().map_err(D::Error::custom)
+    }
+}
+
+//memory writes are an object with the address and value, rather than a pair
+#[cfg(feature = "serde")]
+mod serde_write {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Write {
+        address: u8,
+        value: u8,
+    }
+
+    pub fn serialize<S: Serializer>(write: &Option<(u8, u8)>, s: S) -> Result<S::Ok, S::Error> {
+        write
+            .map(|(address, value)| Write { address, value })
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<(u8, u8)>, D::Error> {
+        let write = Option::<Write>::deserialize(d)?;
+        Ok(write.map(|write| (write.address, write.value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execute_program_traced;
+
+    fn trace(program: &[u8]) -> Vec<TraceEntry> {
+        let mut trace = Vec::new();
+        execute_program_traced(program, 100, |entry| trace.push(entry)).unwrap();
+        trace
+    }
+
+    #[test]
+    fn test_trace() {
+        //ADD 15, STORE 30, STOP
+        let trace = trace(&[0b0100_1111, 0b1111_1110, 1]);
+        assert_eq!(trace.len(), 3);
+        assert_eq!(
+            trace[1],
+            TraceEntry {
+                cycle: 2,
+                pc: 1,
+                instruction: Instruction::Store(30),
+                register_before: 15,
+                register_after: 15,
+                z: false,
+                write: Some((30, 15)),
+            }
+        );
+        //the STOP gets a line too
+        assert_eq!(trace[2].instruction, Instruction::Clear(1));
+    }
+
+    #[test]
+    fn test_formats() {
+        let trace = trace(&[0b0100_1111, 0b1111_1110, 1]);
+
+        assert_eq!(
+            trace[0].format(TraceFormat::Text),
+            "    1 |  0: ADD 15    | A:   0 ->  15 | Z: 0"
+        );
+        assert_eq!(
+            trace[1].format(TraceFormat::Text),
+            "    2 |  1: STORE 30  | A:  15 ->  15 | Z: 0 | [30] <- 15"
+        );
+        assert_eq!(trace[0].format(TraceFormat::Csv), "1,0,ADD 15,0,15,0,,");
+        assert_eq!(
+            trace[1].format(TraceFormat::Csv),
+            "2,1,STORE 30,15,15,0,30,15"
+        );
+        assert_eq!(
+            trace[1].format(TraceFormat::Json),
+            r#"{"cycle":2,"pc":1,"instruction":"STORE 30","register_before":15,"register_after":15,"z":false,"write":{"address":30,"value":15}}"#
+        );
+        assert!(trace[2]
+            .format(TraceFormat::Json)
+            .ends_with(r#""write":null}"#));
+    }
+
+    //the serde derive and the json format are the same thing
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        for entry in trace(&[0b0100_1111, 0b1111_1110, 1]) {
+            let json = entry.format(TraceFormat::Json);
+            assert_eq!(serde_json::to_string(&entry).unwrap(), json);
+            assert_eq!(serde_json::from_str::<TraceEntry>(&json).unwrap(), entry);
+        }
+        let bad = r#"{"cycle":1,"pc":0,"instruction":"ADD 40","register_before":0,"register_after":0,"z":false,"write":null}"#;
+        assert!(serde_json::from_str::<TraceEntry>(bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("CSV".parse(), Ok(TraceFormat::Csv));
+        assert_eq!("json".parse(), Ok(TraceFormat::Json));
+        assert!("xml".parse::<TraceFormat>().is_err());
+    }
+}
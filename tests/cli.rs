@@ -0,0 +1,368 @@
+//runs the patp binary against the example programs, checking everything fits together from the command line
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn patp(args: &[&str]) -> Output {
+    patp_with_input(args, &[])
+}
+
+//runs patp with the given arguments, piping input into it
+fn patp_with_input(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_patp"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "patp failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+//somewhere to write files to that's only used by one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("patp-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn run_examples() {
+    let add = stdout(&patp(&["run", "examples/add.patp"]));
+    assert!(add.starts_with("Final CPU State"));
+    assert!(add.contains("Register: 22"));
+
+    let inc = stdout(&patp(&["run", "examples/inc.patp"]));
+    assert!(inc.contains("Program Counter: 6"));
+    assert!(inc.contains("Register: 1"));
+
+    let looped = stdout(&patp(&["run", "examples/loop.patp"]));
+    assert!(looped.contains("Program Counter: 5"));
+}
+
+//assembling then emulating a program ends up in the same place as running it
+#[test]
+fn assemble_then_emulate() {
+    let dir = temp_dir("assemble");
+    for example in ["add", "inc", "loop"] {
+        let source = format!("examples/{}.patp", example);
+        let binary = dir.join(example);
+        let binary = binary.to_str().unwrap();
+
+        stdout(&patp(&["assemble", &source, "-o", binary]));
+        assert_eq!(
+            stdout(&patp(&["emulate", binary])),
+            stdout(&patp(&["run", &source]))
+        );
+    }
+
+    //by default the binary goes next to the source
+    let source = dir.join("copy.patp");
+    fs::copy("examples/loop.patp", &source).unwrap();
+    stdout(&patp(&["assemble", source.to_str().unwrap()]));
+    assert_eq!(
+        fs::read(dir.join("copy")).unwrap(),
+        fs::read(dir.join("loop")).unwrap()
+    );
+
+    //a listing goes next to the binary
+    let binary = dir.join("listed");
+    stdout(&patp(&[
+        "assemble",
+        "examples/loop.patp",
+        "--output",
+        binary.to_str().unwrap(),
+        "--listing",
+    ]));
+    let listing = fs::read_to_string(binary.with_extension("lst")).unwrap();
+    assert!(listing.contains("loop"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+//every format assemble writes can be run by emulate without being told which it is
+#[test]
+fn formats() {
+    let expected = stdout(&patp(&["run", "examples/add.patp"]));
+    for format in [
+        "raw", "hex", "bin", "ihex", "readmemh", "readmemb", "logisim",
+    ] {
+        let binary = patp(&[
+            "assemble",
+            "examples/add.patp",
+            "-o",
+            "-",
+            "--format",
+            format,
+        ]);
+        assert!(binary.status.success(), "{}", format);
+        assert_eq!(
+            stdout(&patp_with_input(&["emulate", "-"], &binary.stdout)),
+            expected,
+            "{}",
+            format
+        );
+    }
+
+    let logisim = stdout(&patp(&[
+        "assemble",
+        "examples/loop.patp",
+        "-o",
+        "-",
+        "--format",
+        "logisim",
+    ]));
+    assert_eq!(logisim, "v2.0 raw\n00 48 60 a1 01\n");
+
+    let output = patp_with_input(&["emulate", "-"], b":0100000020DE\n");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: Line 1: checksum is DE but should be DF\n"
+    );
+
+    //ADD 1, ADD 2, INC, DB 10 is "AB \n" as raw bytes, which mustn't be mistaken for hex
+    let program = [0x41, 0x42, 0x20, 0x0a];
+    let state = stdout(&patp_with_input(&["emulate", "-"], &program));
+    assert!(state.contains("Register: 4"));
+    assert!(stdout(&patp_with_input(&["disassemble", "-"], &program)).contains("INC"));
+
+    //without the INC it's "AB\n", which does look like hex, so has to be read as raw on purpose
+    let state = stdout(&patp_with_input(
+        &["emulate", "-", "--input-format", "raw"],
+        b"AB\n",
+    ));
+    assert!(state.contains("Register: 3"));
+    let output = patp_with_input(&["emulate", "-", "--input-format", "hex"], &program);
+    assert!(!output.status.success());
+
+    //ADD 1, ADD 2 assembles to "AB", which is run as it was assembled
+    let assembled = patp_with_input(&["assemble", "-", "-o", "-"], b"ADD 1\nADD 2\nSTOP");
+    let state = stdout(&patp_with_input(&["emulate", "-"], &assembled.stdout));
+    assert!(state.contains("Register: 3"));
+
+    //raw programs made only of bytes that look like hex digits run the same as when they're said to be raw
+    let digits: Vec<u8> = (b'0'..=b'9')
+        .chain(b'A'..=b'F')
+        .chain(b'a'..=b'f')
+        .collect();
+    for program in digits.chunks(2).chain([digits.as_slice()]) {
+        let detected = patp_with_input(&["emulate", "-"], program);
+        let raw = patp_with_input(&["emulate", "-", "--input-format", "raw"], program);
+        assert_eq!(detected, raw, "{:?}", program);
+        assert_eq!(
+            patp_with_input(&["disassemble", "-"], program),
+            patp_with_input(&["disassemble", "-", "--input-format", "raw"], program),
+        );
+    }
+}
+
+#[test]
+fn stdin_and_stdout() {
+    let binary = patp(&["assemble", "examples/loop.patp", "-o", "-"]).stdout;
+    assert_eq!(
+        binary,
+        [0b0000_0000, 0b0100_1000, 0b0110_0000, 0b1010_0001, 1]
+    );
+
+    //source from stdin goes to stdout by default
+    let source = fs::read("examples/loop.patp").unwrap();
+    assert_eq!(patp_with_input(&["assemble", "-"], &source).stdout, binary);
+
+    assert_eq!(
+        stdout(&patp_with_input(&["emulate", "-"], &binary)),
+        stdout(&patp_with_input(&["run", "-"], &source))
+    );
+
+    let disassembled = stdout(&patp_with_input(&["disassemble", "-"], &binary));
+    assert_eq!(
+        patp_with_input(&["assemble", "-"], disassembled.as_bytes()).stdout,
+        binary
+    );
+}
+
+#[test]
+fn quiet() {
+    assert_eq!(stdout(&patp(&["run", "examples/loop.patp", "--quiet"])), "");
+    //a trace is still printed, as it was asked for
+    let trace = stdout(&patp(&[
+        "run",
+        "examples/loop.patp",
+        "-q",
+        "--trace",
+        "csv",
+    ]));
+    assert!(trace.starts_with("cycle,"));
+    assert!(!trace.contains("Final CPU State"));
+}
+
+#[test]
+fn errors() {
+    //errors and warnings go to stderr, and errors fail
+    let output = patp_with_input(&["run", "-"], b"ADD 40\nSTOP");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: Invalid operand"));
+    assert!(stderr.contains("1 | ADD 40"));
+
+    let output = patp(&["run", "examples/add.patp"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = patp_with_input(&["run", "-"], b"unused: STOP");
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("warning: "));
+
+    assert!(!patp(&["run", "examples/nothing.patp"]).status.success());
+    assert!(
+        !patp(&["assemble", "examples/add.patp", "--format", "nonsense"])
+            .status
+            .success()
+    );
+}
+
+//modules assembled separately link into a program that runs the same as anything else
+#[test]
+fn link() {
+    let dir = temp_dir("link");
+    let main = dir.join("main.o");
+    let countdown = dir.join("countdown.o");
+    let (main, countdown) = (main.to_str().unwrap(), countdown.to_str().unwrap());
+    stdout(&patp(&[
+        "assemble",
+        "--object",
+        "examples/linked/main.patp",
+        "-o",
+        main,
+    ]));
+    stdout(&patp(&[
+        "assemble",
+        "-c",
+        "examples/linked/countdown.patp",
+        "-o",
+        countdown,
+    ]));
+
+    //by default the program goes next to the first object
+    stdout(&patp(&["link", main, countdown]));
+    let state = stdout(&patp(&["emulate", dir.join("main").to_str().unwrap()]));
+    assert!(state.contains("Program Counter: 4"));
+    assert!(state.contains("Register: 0"));
+
+    let hex = stdout(&patp(&[
+        "link", main, countdown, "-o", "-", "--format", "hex",
+    ]));
+    assert_eq!(hex, "45\n85\ne4\n01\n00\n60\na5\n82\n");
+
+    let output = patp(&["link", main]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("error: Unknown symbol countdown"));
+
+    //externals only mean something in an object
+    assert!(!patp(&["run", "examples/linked/main.patp"]).status.success());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn states() {
+    let json = stdout(&patp(&["run", "examples/add.patp", "--state", "json"]));
+    assert!(json.trim_start().starts_with('{'));
+    assert!(json.contains("\"register\": 22"));
+    let toml = stdout(&patp(&["run", "examples/add.patp", "--state", "toml"]));
+    assert!(toml.contains("register = 22"));
+
+    //INC then STOP, starting part way through with something already in the register
+    let memory: Vec<String> = [0, 0b0010_0000, 1]
+        .iter()
+        .chain([0; 29].iter())
+        .map(|byte| byte.to_string())
+        .collect();
+    let state = format!(
+        "{{\"memory\": [{}], \"z\": false, \"register\": 41, \"pc\": 1}}",
+        memory.join(", ")
+    );
+    let resumed = stdout(&patp_with_input(
+        &["emulate", "--from-state", "-", "--state", "json"],
+        state.as_bytes(),
+    ));
+    assert!(resumed.contains("\"register\": 42"));
+    assert!(resumed.contains("\"pc\": 3"));
+
+    //a program that never stops still has its state printed, with why it stopped, but fails
+    let output = patp_with_input(&["run", "-", "--state", "json"], b"self: JUMP self");
+    assert!(!output.status.success());
+    let json = String::from_utf8(output.stdout).unwrap();
+    assert!(json.contains("\"LoopDetected\": 0"));
+    assert!(json.contains("\"cycles\""));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("loops forever"));
+    let output = patp(&[
+        "run",
+        "examples/add.patp",
+        "--max-cycles",
+        "1",
+        "--state",
+        "toml",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("outcome = \"StepLimit\""));
+
+    let dir = temp_dir("states");
+    //the saved state is just past the STOP, so carrying on from it goes round memory and runs the program again
+    let saved = dir.join("state.toml");
+    fs::write(&saved, toml).unwrap();
+    let output = patp(&["emulate", "--from-state", saved.to_str().unwrap()]);
+    assert!(stdout(&output).contains("Final CPU State"));
+
+    let bad = patp_with_input(
+        &["emulate", "--from-state", "-"],
+        state.replace("\"pc\": 1", "\"pc\": 40").as_bytes(),
+    );
+    assert!(!bad.status.success());
+    assert!(
+        String::from_utf8_lossy(&bad.stderr).contains("program counter 40 is outside of memory")
+    );
+}
+
+//every line of a json trace can be read back in by anything using the library
+#[cfg(feature = "serde")]
+#[test]
+fn json_traces() {
+    let trace = stdout(&patp(&[
+        "run",
+        "examples/inc.patp",
+        "--trace",
+        "json",
+        "-q",
+    ]));
+    let entries: Vec<patp::TraceEntry> = trace
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(!entries.is_empty());
+    assert!(entries.iter().any(|entry| entry.write.is_some()));
+    assert_eq!(
+        entries.last().unwrap().instruction,
+        patp::Instruction::Clear(1)
+    );
+}